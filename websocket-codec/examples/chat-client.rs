@@ -0,0 +1,52 @@
+/*
+    터미널에서 쓰는 채팅 클라이언트.
+
+    cargo run --example chat-client -- ws://127.0.0.1:8080/
+
+    한 줄 치면 보내고, 다른 사람이 보낸 메시지는 받는 대로 찍어줍니다.
+*/
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+use websocket_codec::{
+    client::{connect, ClientOptions},
+    CloseCode, CloseFrame, Message,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let url = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "ws://127.0.0.1:8080/".to_string());
+
+    let options = ClientOptions {
+        permessage_deflate: true,
+        ..ClientOptions::default()
+    };
+    let mut client = connect(&url, options).await?;
+    println!("connected to {url}");
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    // send와 recv는 취소돼도 괜찮아서 select!에 그대로 넣을 수 있어요.
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line? {
+                Some(line) => client.send_text(line).await?,
+                None => {
+                    client.close(CloseFrame::new(CloseCode::NORMAL_CLOSURE, "bye")).await?;
+                    break;
+                }
+            },
+            message = client.recv() => match message? {
+                Some(Message::Text(text)) => println!("{text}"),
+                Some(Message::Binary(bytes)) => println!("[binary {} bytes]", bytes.len()),
+                None => {
+                    println!("closed by server: {:?}", client.close_frame());
+                    break;
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
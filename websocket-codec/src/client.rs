@@ -0,0 +1,667 @@
+use crate::{
+    close::{CloseFrame, ParseCloseFrameError},
+    deflate::{DeflateConfig, Deflater, Inflater, EXTENSION_NAME},
+    fragment,
+    frame::apply_mask,
+    handshake::{generate_key, verify_accept_key, SUPPORTED_VERSION},
+    AssembleError, CloseCode, DecodeError, Decoder, Frame, Message, MessageAssembler, Opcode, Role,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+/*
+    봇이나 테스트, 부하 테스트에서 쓰는 클라이언트.
+
+    서버와 똑같은 Decoder, MessageAssembler를 쓰고 다른 건 딱 두 가지예요.
+    - 보내는 프레임은 전부 마스킹합니다. 키는 프레임마다 새로 뽑아요.
+    - 받는 프레임은 마스킹되어 있으면 안돼요.
+*/
+
+/// 핸드셰이크 응답은 이것보다 클 이유가 없어요.
+const MAX_RESPONSE_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// Sec-WebSocket-Protocol로 제안할 것들. 앞에 있을수록 우리가 더 원하는 거예요.
+    pub subprotocols: Vec<String>,
+    /// permessage-deflate를 제안할지.
+    pub permessage_deflate: bool,
+    /// 핸드셰이크 요청에 더 붙일 헤더. (Origin 같은 것)
+    pub headers: Vec<(String, String)>,
+    pub max_inbound_frame_size: usize,
+    pub max_inbound_message_size: usize,
+    /// 이것보다 큰 메시지는 쪼개서 보내요.
+    pub max_outbound_frame_size: usize,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            subprotocols: vec![],
+            permessage_deflate: false,
+            headers: vec![],
+            max_inbound_frame_size: 1024 * 1024,
+            max_inbound_message_size: 4 * 1024 * 1024,
+            max_outbound_frame_size: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(std::io::Error),
+    /// `ws://host[:port][/path]` 모양이 아니에요.
+    InvalidUrl(String),
+    /// 서버가 핸드셰이크를 받아주지 않았거나, 응답이 잘못됐어요.
+    Handshake(String),
+    Decode(DecodeError),
+    Assemble(AssembleError),
+    /// 서버가 보낸 Close frame이 잘못됐어요.
+    InvalidCloseFrame(ParseCloseFrameError),
+    /// 이미 Close를 보냈어요.
+    Closed,
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Io(error) => write!(f, "IO error: {error}"),
+            ClientError::InvalidUrl(url) => write!(f, "Invalid url: {url}"),
+            ClientError::Handshake(reason) => write!(f, "Handshake failed: {reason}"),
+            ClientError::Decode(error) => write!(f, "{error}"),
+            ClientError::Assemble(error) => write!(f, "{error}"),
+            ClientError::InvalidCloseFrame(error) => write!(f, "Invalid close frame: {error:?}"),
+            ClientError::Closed => write!(f, "Connection is closed"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<std::io::Error> for ClientError {
+    fn from(error: std::io::Error) -> Self {
+        ClientError::Io(error)
+    }
+}
+
+pub struct Client<S> {
+    /// 핸드셰이크 응답 뒤에 바로 붙어온 프레임이 있을 수 있으니 BufReader를 계속 써요.
+    stream: BufReader<S>,
+    decoder: Decoder,
+    assembler: MessageAssembler,
+    deflater: Option<Deflater>,
+    subprotocol: Option<String>,
+    max_outbound_frame_size: usize,
+    /*
+        아직 못보낸 바이트.
+        보낼 프레임은 전부 여기에 먼저 쌓고 나서 소켓에 씁니다.
+        그래서 send나 recv가 select!에서 중간에 취소돼도 프레임이 반만 나가는 일은 없어요.
+        남은 건 다음 send나 recv에서 마저 보냅니다.
+    */
+    outgoing: Vec<u8>,
+    close_sent: bool,
+    close_received: Option<CloseFrame>,
+}
+
+/// `ws://host[:port][/path]`로 연결하고 핸드셰이크까지 합니다.
+pub async fn connect(url: &str, options: ClientOptions) -> Result<Client<TcpStream>, ClientError> {
+    let (host, address, path) = parse_url(url)?;
+    let stream = TcpStream::connect(address).await?;
+    stream.set_nodelay(true)?;
+    Client::handshake(stream, &host, &path, options).await
+}
+
+/// (Host 헤더, 연결할 주소, 경로)
+fn parse_url(url: &str) -> Result<(String, String, String), ClientError> {
+    let invalid_url = || ClientError::InvalidUrl(url.to_string());
+
+    let rest = url.strip_prefix("ws://").ok_or_else(invalid_url)?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(invalid_url());
+    }
+
+    // [::1]:8080 처럼 IPv6 주소 안에도 ':'가 있어요.
+    let has_port = authority
+        .rsplit_once(':')
+        .is_some_and(|(_, port)| !port.contains(']'));
+    let address = if has_port {
+        authority.to_string()
+    } else {
+        format!("{authority}:80")
+    };
+
+    Ok((authority.to_string(), address, path.to_string()))
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Client<S> {
+    /// 이미 연결된 스트림 위에서 핸드셰이크를 합니다.
+    pub async fn handshake(
+        mut stream: S,
+        host: &str,
+        path: &str,
+        options: ClientOptions,
+    ) -> Result<Self, ClientError> {
+        let key = generate_key(rand::random());
+
+        let mut request = format!("GET {path} HTTP/1.1\r\n");
+        request.push_str(&format!("Host: {host}\r\n"));
+        request.push_str("Upgrade: websocket\r\n");
+        request.push_str("Connection: Upgrade\r\n");
+        request.push_str(&format!("Sec-WebSocket-Key: {key}\r\n"));
+        request.push_str(&format!("Sec-WebSocket-Version: {SUPPORTED_VERSION}\r\n"));
+        if !options.subprotocols.is_empty() {
+            request.push_str(&format!(
+                "Sec-WebSocket-Protocol: {}\r\n",
+                options.subprotocols.join(", ")
+            ));
+        }
+        if options.permessage_deflate {
+            request.push_str(&format!("Sec-WebSocket-Extensions: {EXTENSION_NAME}\r\n"));
+        }
+        for (name, value) in &options.headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+        // TLS 위라면 flush해야 실제로 나가요.
+        stream.flush().await?;
+
+        let mut stream = BufReader::new(stream);
+        let (status_line, headers) = read_response(&mut stream).await?;
+        let header_values = |name: &str| {
+            headers
+                .iter()
+                .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+                .collect::<Vec<_>>()
+        };
+        let has_header_token = |name: &str, token: &str| {
+            header_values(name)
+                .iter()
+                .flat_map(|value| value.split(','))
+                .any(|value| value.trim().eq_ignore_ascii_case(token))
+        };
+
+        // RFC 6455 4.1. 클라이언트가 응답에서 확인해야 하는 것들
+        if status_line.split_whitespace().nth(1) != Some("101") {
+            return Err(ClientError::Handshake(format!(
+                "Unexpected status: {status_line}"
+            )));
+        }
+        if !has_header_token("Upgrade", "websocket") {
+            return Err(ClientError::Handshake(
+                "Upgrade header must contain websocket".to_string(),
+            ));
+        }
+        if !has_header_token("Connection", "Upgrade") {
+            return Err(ClientError::Handshake(
+                "Connection header must contain Upgrade".to_string(),
+            ));
+        }
+        let accept_keys = header_values("Sec-WebSocket-Accept");
+        if !matches!(accept_keys[..], [accept_key] if verify_accept_key(&key, accept_key)) {
+            return Err(ClientError::Handshake(
+                "Sec-WebSocket-Accept does not match".to_string(),
+            ));
+        }
+
+        // 제안하지 않은 걸 골라오면 안돼요.
+        let subprotocol = match header_values("Sec-WebSocket-Protocol")[..] {
+            [] => None,
+            [subprotocol]
+                if options
+                    .subprotocols
+                    .iter()
+                    .any(|offered| offered == subprotocol) =>
+            {
+                Some(subprotocol.to_string())
+            }
+            _ => {
+                return Err(ClientError::Handshake(
+                    "Server selected a subprotocol we did not offer".to_string(),
+                ))
+            }
+        };
+        let deflate = match header_values("Sec-WebSocket-Extensions")[..] {
+            [] => None,
+            [extension] if options.permessage_deflate => {
+                Some(DeflateConfig::from_response(extension).ok_or_else(|| {
+                    ClientError::Handshake(format!("Unexpected extension: {extension}"))
+                })?)
+            }
+            _ => {
+                return Err(ClientError::Handshake(
+                    "Server selected an extension we did not offer".to_string(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            stream,
+            decoder: Decoder::new(Role::Client)
+                .with_max_frame_size(options.max_inbound_frame_size)
+                .allow_rsv1(deflate.is_some()),
+            assembler: MessageAssembler::new(
+                deflate
+                    .as_ref()
+                    .map(|deflate| Inflater::new(deflate, Role::Client)),
+                options.max_inbound_message_size,
+            ),
+            deflater: deflate
+                .as_ref()
+                .map(|deflate| Deflater::new(deflate, Role::Client)),
+            subprotocol,
+            max_outbound_frame_size: options.max_outbound_frame_size,
+            outgoing: Vec::new(),
+            close_sent: false,
+            close_received: None,
+        })
+    }
+
+    /// 서버가 고른 subprotocol.
+    pub fn subprotocol(&self) -> Option<&str> {
+        self.subprotocol.as_deref()
+    }
+
+    /// 서버가 보낸 Close frame. 아직 안왔으면 None.
+    pub fn close_frame(&self) -> Option<&CloseFrame> {
+        self.close_received.as_ref()
+    }
+
+    pub async fn send(&mut self, message: &Message) -> Result<(), ClientError> {
+        if self.close_sent {
+            return Err(ClientError::Closed);
+        }
+
+        let (opcode, payload) = match message {
+            Message::Text(text) => (Opcode::Text, text.as_bytes()),
+            Message::Binary(bytes) => (Opcode::Binary, &bytes[..]),
+        };
+        let compressed;
+        let payload = match &mut self.deflater {
+            Some(deflater) => {
+                compressed = deflater.compress(payload)?;
+                &compressed[..]
+            }
+            None => payload,
+        };
+
+        for (mut header, chunk) in fragment(
+            opcode,
+            self.deflater.is_some(),
+            payload,
+            self.max_outbound_frame_size,
+        ) {
+            let mask = rand::random();
+            header.mask = Some(mask);
+            self.outgoing.extend_from_slice(&header.encode());
+            let payload_start = self.outgoing.len();
+            self.outgoing.extend_from_slice(chunk);
+            apply_mask(&mut self.outgoing[payload_start..], mask);
+        }
+
+        self.flush().await
+    }
+
+    pub async fn send_text(&mut self, text: impl Into<String>) -> Result<(), ClientError> {
+        self.send(&Message::Text(text.into())).await
+    }
+
+    /// Pong은 recv를 부르고 있어야 받을 수 있어요. 받아도 따로 알려주지는 않습니다.
+    pub async fn ping(&mut self, payload: &[u8]) -> Result<(), ClientError> {
+        if self.close_sent {
+            return Err(ClientError::Closed);
+        }
+        self.queue_control_frame(Opcode::Ping, payload.to_vec());
+        self.flush().await
+    }
+
+    /*
+        다음 메시지를 기다립니다.
+        그 사이에 온 Ping에는 알아서 Pong을 보내요.
+        서버가 Close를 보내면 똑같은 코드로 Close를 돌려주고 None을 돌려줍니다.
+    */
+    pub async fn recv(&mut self) -> Result<Option<Message>, ClientError> {
+        self.flush().await?;
+
+        loop {
+            if self.close_received.is_some() {
+                return Ok(None);
+            }
+
+            let frame = self.read_frame().await?;
+            match frame.opcode {
+                Opcode::Ping => {
+                    // Close를 보낸 뒤로는 아무것도 보내면 안돼요.
+                    if !self.close_sent {
+                        self.queue_control_frame(Opcode::Pong, frame.payload);
+                        self.flush().await?;
+                    }
+                }
+                Opcode::Pong => {}
+                Opcode::Close => {
+                    let close_frame = match CloseFrame::parse(&frame.payload) {
+                        Ok(close_frame) => close_frame,
+                        Err(error) => {
+                            let code = match error {
+                                ParseCloseFrameError::Protocol(_) => CloseCode::PROTOCOL_ERROR,
+                                ParseCloseFrameError::InvalidUtf8 => CloseCode::INVALID_DATA,
+                            };
+                            return Err(self
+                                .fail(code, ClientError::InvalidCloseFrame(error))
+                                .await);
+                        }
+                    };
+
+                    if !self.close_sent {
+                        self.close_sent = true;
+                        self.queue_control_frame(
+                            Opcode::Close,
+                            CloseFrame {
+                                code: close_frame.code,
+                                reason: String::new(),
+                            }
+                            .to_payload(),
+                        );
+                    }
+                    self.close_received = Some(close_frame);
+                    self.flush().await?;
+                    self.stream.shutdown().await?;
+                    return Ok(None);
+                }
+                Opcode::Continuation | Opcode::Text | Opcode::Binary => {
+                    match self.assembler.push(frame) {
+                        Ok(Some(message)) => return Ok(Some(message)),
+                        Ok(None) => {}
+                        Err(error) => {
+                            return Err(self
+                                .fail(error.close_code(), ClientError::Assemble(error))
+                                .await)
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Close를 보내고 서버의 Close를 기다립니다. 그 사이에 온 메시지는 버려요.
+    pub async fn close(&mut self, close_frame: CloseFrame) -> Result<(), ClientError> {
+        if !self.close_sent {
+            self.close_sent = true;
+            self.queue_control_frame(Opcode::Close, close_frame.to_payload());
+        }
+
+        while self.close_received.is_none() {
+            self.recv().await?;
+        }
+
+        Ok(())
+    }
+
+    /// 프레임 하나가 다 올 때까지 읽습니다. 취소돼도 읽은 바이트는 Decoder에 남아있어요.
+    async fn read_frame(&mut self) -> Result<Frame, ClientError> {
+        loop {
+            match self.decoder.decode() {
+                Ok(Some(frame)) => return Ok(frame),
+                Ok(None) => {}
+                Err(error) => {
+                    return Err(self
+                        .fail(error.close_code(), ClientError::Decode(error))
+                        .await)
+                }
+            }
+
+            let buf = self.stream.fill_buf().await?;
+            if buf.is_empty() {
+                return Err(ClientError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.decoder.feed(buf);
+            let length = buf.len();
+            self.stream.consume(length);
+        }
+    }
+
+    fn queue_control_frame(&mut self, opcode: Opcode, payload: Vec<u8>) {
+        self.outgoing
+            .extend_from_slice(&Frame::new(opcode, payload).encode(Some(rand::random())));
+    }
+
+    async fn flush(&mut self) -> Result<(), ClientError> {
+        while !self.outgoing.is_empty() {
+            // write_all은 취소되면 어디까지 썼는지 알 수 없어서, 쓴 만큼씩 지워요.
+            let written = self.stream.get_mut().write(&self.outgoing).await?;
+            if written == 0 {
+                return Err(ClientError::Io(std::io::ErrorKind::WriteZero.into()));
+            }
+            self.outgoing.drain(..written);
+        }
+        self.stream.get_mut().flush().await?;
+        Ok(())
+    }
+
+    /// 서버가 규칙을 어겼어요. 이유를 담아 Close를 보내고 끊습니다.
+    async fn fail(&mut self, code: CloseCode, error: ClientError) -> ClientError {
+        if !self.close_sent {
+            self.close_sent = true;
+            self.queue_control_frame(
+                Opcode::Close,
+                CloseFrame::new(code, error.to_string()).to_payload(),
+            );
+            let _ = self.flush().await;
+        }
+        error
+    }
+}
+
+/// 상태줄과 헤더들. 본문은 없어요.
+async fn read_response(
+    stream: &mut (impl tokio::io::AsyncBufRead + Unpin),
+) -> Result<(String, Vec<(String, String)>), ClientError> {
+    let mut status_line = None;
+    let mut headers = vec![];
+    let mut remaining = MAX_RESPONSE_SIZE;
+
+    loop {
+        let mut line = Vec::new();
+        (&mut *stream)
+            .take(remaining as u64)
+            .read_until(b'\n', &mut line)
+            .await?;
+        if !line.ends_with(b"\n") {
+            return Err(ClientError::Handshake(
+                "Response ended or got too large before the headers finished".to_string(),
+            ));
+        }
+        remaining -= line.len();
+
+        let line = String::from_utf8(line)
+            .map_err(|_| ClientError::Handshake("Response is not valid UTF-8".to_string()))?;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+
+        if status_line.is_none() {
+            status_line = Some(line.to_string());
+            continue;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| ClientError::Handshake(format!("Malformed header: {line}")))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+
+    let status_line =
+        status_line.ok_or_else(|| ClientError::Handshake("Empty response".to_string()))?;
+    Ok((status_line, headers))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handshake::generate_accept_key;
+    use tokio::io::{AsyncBufReadExt, DuplexStream};
+
+    /// 클라이언트 요청을 읽고 101을 돌려주는 가짜 서버. 요청 헤더들을 돌려줘요.
+    async fn accept(
+        server: &mut BufReader<DuplexStream>,
+        extra_headers: &str,
+        corrupt_accept_key: bool,
+    ) -> Vec<String> {
+        let mut request = vec![];
+        loop {
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            let line = line.trim_end().to_string();
+            if line.is_empty() {
+                break;
+            }
+            request.push(line);
+        }
+
+        let key = request
+            .iter()
+            .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+            .unwrap();
+        let mut accept_key = generate_accept_key(key);
+        if corrupt_accept_key {
+            accept_key.insert(0, 'x');
+        }
+        server
+            .write_all(
+                format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                     Sec-WebSocket-Accept: {accept_key}\r\n{extra_headers}\r\n"
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        request
+    }
+
+    async fn read_frame(server: &mut BufReader<DuplexStream>, decoder: &mut Decoder) -> Frame {
+        loop {
+            if let Some(frame) = decoder.decode().unwrap() {
+                return frame;
+            }
+            let buf = server.fill_buf().await.unwrap();
+            assert!(!buf.is_empty());
+            decoder.feed(buf);
+            let length = buf.len();
+            server.consume(length);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_round_trip() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut server = BufReader::new(server);
+
+        let server_task = tokio::spawn(async move {
+            let request = accept(&mut server, "Sec-WebSocket-Protocol: chat.v1\r\n", false).await;
+            assert!(request.contains(&"Sec-WebSocket-Protocol: chat.v1, chat.v0".to_string()));
+
+            // 서버 쪽 Decoder는 마스킹 안된 프레임을 거절하니, 여기서 읽히면 마스킹된 거예요.
+            let mut decoder = Decoder::new(Role::Server);
+            let frame = read_frame(&mut server, &mut decoder).await;
+            assert_eq!(frame, Frame::new(Opcode::Text, b"hello".to_vec()));
+
+            server
+                .write_all(&Frame::new(Opcode::Ping, b"are you there".to_vec()).encode(None))
+                .await
+                .unwrap();
+            server
+                .write_all(&Frame::new(Opcode::Text, b"world".to_vec()).encode(None))
+                .await
+                .unwrap();
+            let frame = read_frame(&mut server, &mut decoder).await;
+            assert_eq!(frame, Frame::new(Opcode::Pong, b"are you there".to_vec()));
+
+            let close_frame = CloseFrame::new(CloseCode::GOING_AWAY, "bye");
+            server
+                .write_all(&Frame::new(Opcode::Close, close_frame.to_payload()).encode(None))
+                .await
+                .unwrap();
+            let frame = read_frame(&mut server, &mut decoder).await;
+            assert_eq!(frame.opcode, Opcode::Close);
+            assert_eq!(
+                CloseFrame::parse(&frame.payload).unwrap().code,
+                Some(CloseCode::GOING_AWAY)
+            );
+        });
+
+        let options = ClientOptions {
+            subprotocols: vec!["chat.v1".to_string(), "chat.v0".to_string()],
+            ..ClientOptions::default()
+        };
+        let mut client = Client::handshake(client, "localhost", "/", options)
+            .await
+            .unwrap();
+        assert_eq!(client.subprotocol(), Some("chat.v1"));
+
+        client.send_text("hello").await.unwrap();
+        assert_eq!(
+            client.recv().await.unwrap(),
+            Some(Message::Text("world".to_string()))
+        );
+        assert_eq!(client.recv().await.unwrap(), None);
+        assert_eq!(
+            client.close_frame(),
+            Some(&CloseFrame::new(CloseCode::GOING_AWAY, "bye"))
+        );
+        assert!(matches!(
+            client.send_text("too late").await,
+            Err(ClientError::Closed)
+        ));
+
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_rejects_wrong_accept_key() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut server = BufReader::new(server);
+        tokio::spawn(async move {
+            accept(&mut server, "", true).await;
+        });
+
+        assert!(matches!(
+            Client::handshake(client, "localhost", "/", ClientOptions::default()).await,
+            Err(ClientError::Handshake(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let parse = |url| parse_url(url).ok();
+        assert_eq!(
+            parse("ws://localhost:8080/rooms/a"),
+            Some((
+                "localhost:8080".to_string(),
+                "localhost:8080".to_string(),
+                "/rooms/a".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("ws://example.com"),
+            Some((
+                "example.com".to_string(),
+                "example.com:80".to_string(),
+                "/".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("ws://[::1]"),
+            Some(("[::1]".to_string(), "[::1]:80".to_string(), "/".to_string()))
+        );
+        assert_eq!(parse("http://localhost"), None);
+        assert_eq!(parse("ws:///path"), None);
+    }
+}
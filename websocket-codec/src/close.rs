@@ -0,0 +1,144 @@
+/// Close frame에 담기는 상태 코드. (RFC 6455 7.4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CloseCode(pub u16);
+
+impl CloseCode {
+    pub const NORMAL_CLOSURE: CloseCode = CloseCode(1000);
+    pub const GOING_AWAY: CloseCode = CloseCode(1001);
+    pub const PROTOCOL_ERROR: CloseCode = CloseCode(1002);
+    pub const UNSUPPORTED_DATA: CloseCode = CloseCode(1003);
+    pub const INVALID_DATA: CloseCode = CloseCode(1007);
+    pub const POLICY_VIOLATION: CloseCode = CloseCode(1008);
+    pub const MESSAGE_TOO_BIG: CloseCode = CloseCode(1009);
+    pub const INTERNAL_ERROR: CloseCode = CloseCode(1011);
+
+    /// 상대가 Close frame에 담아 보내도 되는 코드인지.
+    /// 1005, 1006, 1015처럼 "프레임에 담으면 안되는" 코드나 예약된 코드는 안됩니다.
+    fn is_valid_on_wire(self) -> bool {
+        matches!(self.0, 1000..=1003 | 1007..=1014 | 3000..=4999)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    /// payload가 비어있는 Close frame이면 None.
+    pub code: Option<CloseCode>,
+    pub reason: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseCloseFrameError {
+    /// 길이가 1바이트이거나, 보내면 안되는 코드를 보냈어요.
+    Protocol(String),
+    /// reason이 UTF-8이 아니에요.
+    InvalidUtf8,
+}
+
+impl CloseFrame {
+    pub fn new(code: CloseCode, reason: impl Into<String>) -> Self {
+        Self {
+            code: Some(code),
+            reason: reason.into(),
+        }
+    }
+
+    /*
+        Close frame의 payload
+        +--------------------+-----------------------------+
+        | status code (2바이트) | reason (UTF-8, 나머지 전부) |
+        +--------------------+-----------------------------+
+        둘 다 없어도(payload 0바이트) 됩니다.
+    */
+    pub fn parse(payload: &[u8]) -> Result<Self, ParseCloseFrameError> {
+        match payload.len() {
+            0 => {
+                return Ok(Self {
+                    code: None,
+                    reason: String::new(),
+                })
+            }
+            1 => {
+                return Err(ParseCloseFrameError::Protocol(
+                    "Close frame payload must not be 1 byte".to_string(),
+                ))
+            }
+            _ => {}
+        }
+
+        let code = CloseCode(u16::from_be_bytes([payload[0], payload[1]]));
+        if !code.is_valid_on_wire() {
+            return Err(ParseCloseFrameError::Protocol(format!(
+                "Invalid close code: {}",
+                code.0
+            )));
+        }
+
+        let reason = std::str::from_utf8(&payload[2..])
+            .map_err(|_| ParseCloseFrameError::InvalidUtf8)?
+            .to_string();
+
+        Ok(Self {
+            code: Some(code),
+            reason,
+        })
+    }
+
+    pub fn to_payload(&self) -> Vec<u8> {
+        let Some(code) = self.code else {
+            return vec![];
+        };
+
+        // Control frame payload는 125바이트까지라서, 코드 2바이트를 빼면 reason은 123바이트까지.
+        let mut reason_len = self.reason.len().min(123);
+        while !self.reason.is_char_boundary(reason_len) {
+            reason_len -= 1;
+        }
+
+        let mut payload = Vec::with_capacity(2 + reason_len);
+        payload.extend_from_slice(&code.0.to_be_bytes());
+        payload.extend_from_slice(&self.reason.as_bytes()[..reason_len]);
+        payload
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_close_frame() {
+        assert_eq!(
+            CloseFrame::parse(&[]),
+            Ok(CloseFrame {
+                code: None,
+                reason: String::new()
+            })
+        );
+        assert_eq!(
+            CloseFrame::parse(&[0x03, 0xe8, b'b', b'y', b'e']),
+            Ok(CloseFrame::new(CloseCode::NORMAL_CLOSURE, "bye"))
+        );
+        assert!(matches!(
+            CloseFrame::parse(&[0x03]),
+            Err(ParseCloseFrameError::Protocol(_))
+        ));
+        // 1005는 "코드가 없었다"는 뜻이라 프레임에 담으면 안돼요.
+        assert!(matches!(
+            CloseFrame::parse(&[0x03, 0xed]),
+            Err(ParseCloseFrameError::Protocol(_))
+        ));
+        assert_eq!(
+            CloseFrame::parse(&[0x03, 0xe8, 0xff]),
+            Err(ParseCloseFrameError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn test_close_frame_reason_is_truncated_on_char_boundary() {
+        let frame = CloseFrame::new(CloseCode::INTERNAL_ERROR, "가".repeat(100));
+        let payload = frame.to_payload();
+
+        assert_eq!(payload.len(), 2 + 123);
+        assert!(std::str::from_utf8(&payload[2..]).is_ok());
+    }
+}
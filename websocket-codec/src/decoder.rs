@@ -0,0 +1,336 @@
+use crate::{
+    close::CloseCode,
+    frame::{apply_mask, Frame, FrameHeader, Opcode},
+    Role,
+};
+
+/*
+    받은 바이트를 프레임으로 바꿉니다.
+
+    TCP는 스트림이라 프레임이 한번에 올 수도, 여러번에 나눠서 올 수도, 여러 프레임이 한번에 올 수도 있어요.
+    그래서 받은 바이트는 일단 전부 buffer에 쌓아두고,
+    프레임 하나가 통째로 모였을 때만 꺼내줍니다. 덜 모였으면 그대로 두고 다음 바이트를 기다려요.
+    함수가 중간에 끝나도 읽은 바이트가 사라지지 않는 이유예요.
+*/
+pub struct Decoder {
+    role: Role,
+    buffer: Vec<u8>,
+    max_frame_size: usize,
+    allow_rsv1: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// 합의한 확장이 없는데 RSV 비트가 켜져 있어요.
+    ReservedBits,
+    /// 3~7, 11~15처럼 정의되지 않은 opcode.
+    ReservedOpcode(u8),
+    /// 클라이언트가 보낸 프레임은 마스킹되어야 하고, 서버가 보낸 프레임은 마스킹되면 안돼요.
+    InvalidMask,
+    /// Control frame이 쪼개져 있거나 125바이트보다 길어요.
+    InvalidControlFrame,
+    /// 64비트 길이의 맨 앞 비트가 켜져 있어요.
+    InvalidPayloadLength,
+    /// 설정한 크기 제한을 넘었어요.
+    FrameTooBig { max_frame_size: usize },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::ReservedBits => {
+                write!(f, "RSV1 is only allowed on the first frame of a compressed message, RSV2 and RSV3 must be 0")
+            }
+            DecodeError::ReservedOpcode(opcode) => write!(f, "Not supported opcode: {opcode}"),
+            DecodeError::InvalidMask => write!(f, "Frame masking does not match the sender's role"),
+            DecodeError::InvalidControlFrame => {
+                write!(
+                    f,
+                    "Control frames must not be fragmented or longer than 125 bytes"
+                )
+            }
+            DecodeError::InvalidPayloadLength => {
+                write!(f, "Most significant bit of payload length must be 0")
+            }
+            DecodeError::FrameTooBig { max_frame_size } => {
+                write!(f, "Frame must not be larger than {max_frame_size} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl DecodeError {
+    /// 이 에러로 연결을 닫을 때 Close frame에 담을 코드.
+    pub fn close_code(&self) -> CloseCode {
+        match self {
+            DecodeError::FrameTooBig { .. } => CloseCode::MESSAGE_TOO_BIG,
+            _ => CloseCode::PROTOCOL_ERROR,
+        }
+    }
+}
+
+impl Decoder {
+    /// `role`은 받는 쪽이 누구인지예요. 서버라면 마스킹된 프레임만 받습니다.
+    pub fn new(role: Role) -> Self {
+        Self {
+            role,
+            buffer: Vec::new(),
+            max_frame_size: usize::MAX,
+            allow_rsv1: false,
+        }
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// permessage-deflate를 합의했으면 켜주세요. 메시지 첫 프레임의 RSV1을 허락합니다.
+    pub fn allow_rsv1(mut self, allow_rsv1: bool) -> Self {
+        self.allow_rsv1 = allow_rsv1;
+        self
+    }
+
+    /// 소켓에서 읽은 바이트를 넣어주세요.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// 아직 프레임이 되지 못하고 쌓여있는 바이트 수.
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// 프레임 하나가 다 모였으면 꺼내줍니다. 아직 덜 왔으면 None.
+    ///
+    /// 에러가 나면 그 연결은 더 쓸 수 없어요. 버퍼가 어디서 끊겼는지 모르니까요.
+    pub fn decode(&mut self) -> Result<Option<Frame>, DecodeError> {
+        let Some((header, header_length)) = self.decode_header()? else {
+            return Ok(None);
+        };
+
+        let frame_length = header_length + header.payload_length as usize;
+        if self.buffer.len() < frame_length {
+            return Ok(None);
+        }
+
+        let mut payload = self.buffer[header_length..frame_length].to_vec();
+        self.buffer.drain(..frame_length);
+        if let Some(mask) = header.mask {
+            apply_mask(&mut payload, mask);
+        }
+
+        Ok(Some(Frame {
+            fin: header.fin,
+            rsv1: header.rsv1,
+            rsv2: header.rsv2,
+            rsv3: header.rsv3,
+            opcode: header.opcode,
+            payload,
+        }))
+    }
+
+    /// 헤더만 읽어봅니다. 버퍼는 건드리지 않아요.
+    fn decode_header(&self) -> Result<Option<(FrameHeader, usize)>, DecodeError> {
+        let [first, second, ..] = self.buffer[..] else {
+            return Ok(None);
+        };
+
+        let fin = first & 0b1000_0000 != 0;
+        let rsv1 = first & 0b0100_0000 != 0;
+        let rsv2 = first & 0b0010_0000 != 0;
+        let rsv3 = first & 0b0001_0000 != 0;
+        let opcode = first & 0b0000_1111;
+        let masked = second & 0b1000_0000 != 0;
+        let core_payload_length = second & 0b0111_1111;
+
+        let opcode = Opcode::from_u8(opcode).ok_or(DecodeError::ReservedOpcode(opcode))?;
+
+        /*
+            RSV 비트들은 확장이 쓰라고 비워둔 자리예요.
+            우리가 아는 확장은 permessage-deflate 하나고, 그건 RSV1만 씁니다.
+            그것도 메시지의 첫 프레임(Text, Binary)에만 켤 수 있어요.
+        */
+        if rsv2 || rsv3 {
+            return Err(DecodeError::ReservedBits);
+        }
+        if rsv1 && (!self.allow_rsv1 || !matches!(opcode, Opcode::Text | Opcode::Binary)) {
+            return Err(DecodeError::ReservedBits);
+        }
+
+        let expect_mask = self.role == Role::Server;
+        if masked != expect_mask {
+            return Err(DecodeError::InvalidMask);
+        }
+
+        // Control frame(Close, Ping, Pong)은 쪼개질 수 없고, payload도 125바이트까지만 돼요.
+        // 대신 쪼개진 메시지 중간에 끼어들 수는 있습니다.
+        if opcode.is_control() && (!fin || core_payload_length > 125) {
+            return Err(DecodeError::InvalidControlFrame);
+        }
+
+        let extended_length_size = match core_payload_length {
+            126 => 2,
+            127 => 8,
+            _ => 0,
+        };
+        if self.buffer.len() < 2 + extended_length_size {
+            return Ok(None);
+        }
+
+        let extended = &self.buffer[2..2 + extended_length_size];
+        let payload_length = match core_payload_length {
+            126 => u16::from_be_bytes([extended[0], extended[1]]) as u64,
+            127 => u64::from_be_bytes(extended.try_into().unwrap()),
+            length => length as u64,
+        };
+
+        // 64비트 길이의 맨 앞 비트는 항상 0이어야 해요.
+        if payload_length >> 63 != 0 {
+            return Err(DecodeError::InvalidPayloadLength);
+        }
+
+        /*
+            길이는 상대가 적어 보낸 숫자일 뿐이라 믿으면 안돼요.
+            "2^63바이트 보낼게요" 한마디에 그만큼 메모리를 잡으려고 하면 죽습니다.
+            그래서 길이를 알자마자, 나머지가 오길 기다리기 전에 제한부터 확인해요.
+        */
+        if payload_length > self.max_frame_size as u64 {
+            return Err(DecodeError::FrameTooBig {
+                max_frame_size: self.max_frame_size,
+            });
+        }
+
+        let mask_size = if masked { 4 } else { 0 };
+        let header_length = 2 + extended_length_size + mask_size;
+        if self.buffer.len() < header_length {
+            return Ok(None);
+        }
+
+        let mask = masked.then(|| {
+            let start = 2 + extended_length_size;
+            self.buffer[start..start + 4].try_into().unwrap()
+        });
+
+        Ok(Some((
+            FrameHeader {
+                fin,
+                rsv1,
+                rsv2,
+                rsv3,
+                opcode,
+                mask,
+                payload_length,
+            },
+            header_length,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MASK: [u8; 4] = [0x37, 0xfa, 0x21, 0x3d];
+
+    #[test]
+    fn test_decode_frame_fed_byte_by_byte() {
+        let bytes = [
+            Frame::new(Opcode::Text, b"Hello".to_vec()).encode(Some(MASK)),
+            Frame::new(Opcode::Ping, vec![]).encode(Some(MASK)),
+        ]
+        .concat();
+
+        let mut decoder = Decoder::new(Role::Server);
+        let mut frames = vec![];
+        for byte in bytes {
+            decoder.feed(&[byte]);
+            if let Some(frame) = decoder.decode().unwrap() {
+                frames.push(frame);
+            }
+        }
+
+        assert_eq!(
+            frames,
+            vec![
+                Frame::new(Opcode::Text, b"Hello".to_vec()),
+                Frame::new(Opcode::Ping, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_extended_payload_length() {
+        let frame = Frame::new(Opcode::Binary, vec![7; 70000]);
+        let mut decoder = Decoder::new(Role::Client);
+        decoder.feed(&frame.encode(None));
+        assert_eq!(decoder.decode(), Ok(Some(frame)));
+        assert_eq!(decoder.decode(), Ok(None));
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_frames() {
+        let decode = |role: Role, bytes: &[u8]| {
+            let mut decoder = Decoder::new(role).with_max_frame_size(1000);
+            decoder.feed(bytes);
+            decoder.decode()
+        };
+
+        // 서버는 마스킹 안된 프레임을 받으면 안되고, 클라이언트는 그 반대.
+        assert_eq!(
+            decode(Role::Server, &[0x81, 0x00]),
+            Err(DecodeError::InvalidMask)
+        );
+        assert_eq!(
+            decode(Role::Client, &[0x81, 0x80, 0, 0, 0, 0]),
+            Err(DecodeError::InvalidMask)
+        );
+        assert_eq!(
+            decode(Role::Client, &[0x83, 0x00]),
+            Err(DecodeError::ReservedOpcode(3))
+        );
+        assert_eq!(
+            decode(Role::Client, &[0xC1, 0x00]),
+            Err(DecodeError::ReservedBits)
+        );
+        assert_eq!(
+            decode(Role::Client, &[0x91, 0x00]),
+            Err(DecodeError::ReservedBits)
+        );
+        // 쪼개진 Ping
+        assert_eq!(
+            decode(Role::Client, &[0x09, 0x00]),
+            Err(DecodeError::InvalidControlFrame)
+        );
+        assert_eq!(
+            decode(Role::Client, &[0x82, 127, 0x80, 0, 0, 0, 0, 0, 0, 0]),
+            Err(DecodeError::InvalidPayloadLength)
+        );
+        // payload는 물론이고 마스킹 키가 오기도 전에 거절해요.
+        assert_eq!(
+            decode(Role::Client, &[0x82, 126, 0x10, 0x00]),
+            Err(DecodeError::FrameTooBig {
+                max_frame_size: 1000
+            })
+        );
+        assert_eq!(
+            decode(Role::Server, &[0x82, 0xFE, 0x10, 0x00]),
+            Err(DecodeError::FrameTooBig {
+                max_frame_size: 1000
+            })
+        );
+    }
+
+    #[test]
+    fn test_decode_allows_rsv1_only_when_negotiated() {
+        let mut decoder = Decoder::new(Role::Client).allow_rsv1(true);
+        decoder.feed(&[0xC1, 0x00]);
+        assert!(decoder.decode().unwrap().unwrap().rsv1);
+
+        // continuation frame에는 안돼요.
+        decoder.feed(&[0x40, 0x00]);
+        assert_eq!(decoder.decode(), Err(DecodeError::ReservedBits));
+    }
+}
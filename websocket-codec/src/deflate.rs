@@ -0,0 +1,375 @@
+use crate::Role;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+/*
+    permessage-deflate (RFC 7692)
+
+    메시지 하나의 payload 전체를 raw DEFLATE로 압축해서 보냅니다.
+    압축된 메시지는 첫 프레임의 RSV1 비트를 켜서 표시해요.
+
+    핸드셰이크에서 이런 식으로 주고받습니다.
+    클라이언트: Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits
+    서버:       Sec-WebSocket-Extensions: permessage-deflate
+*/
+
+pub const EXTENSION_NAME: &str = "permessage-deflate";
+
+/// 압축된 메시지 끝에 항상 붙는 4바이트. 보낼 땐 떼고, 받으면 다시 붙여서 풉니다.
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// 핸드셰이크에서 합의한 permessage-deflate 설정.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeflateConfig {
+    /// 서버는 메시지마다 압축 사전을 초기화해야 해요.
+    pub server_no_context_takeover: bool,
+    /// 클라이언트가 메시지마다 압축 사전을 초기화할거예요.
+    pub client_no_context_takeover: bool,
+    /// 서버가 압축할 때 쓸 LZ77 윈도우 크기(2^n).
+    pub server_max_window_bits: u8,
+}
+
+impl DeflateConfig {
+    /// `Sec-WebSocket-Extensions` 헤더 값들에서 받아들일 수 있는 첫번째 offer를 고릅니다.
+    /// 받아들일 수 있는 게 없으면 None. 그럼 압축 없이 연결해요.
+    pub fn negotiate<'a>(header_values: impl Iterator<Item = &'a str>) -> Option<Self> {
+        header_values
+            .flat_map(|value| value.split(','))
+            .find_map(|offer| {
+                let mut params = offer.split(';').map(str::trim);
+                if params.next()? != EXTENSION_NAME {
+                    return None;
+                }
+                Self::from_offer_params(params)
+            })
+    }
+
+    /// 클라이언트 쪽에서, 서버가 돌려준 Sec-WebSocket-Extensions를 읽습니다.
+    /// 우리가 제안한 건 파라미터 없는 `permessage-deflate` 뿐이에요.
+    pub fn from_response(value: &str) -> Option<Self> {
+        let mut params = value.split(';').map(str::trim);
+        if params.next()? != EXTENSION_NAME {
+            return None;
+        }
+
+        // client_max_window_bits는 클라이언트가 제안했을 때만 돌려줄 수 있어요.
+        let params = params.collect::<Vec<_>>();
+        if params
+            .iter()
+            .any(|param| param.starts_with("client_max_window_bits"))
+        {
+            return None;
+        }
+        Self::from_offer_params(params.into_iter())
+    }
+
+    fn from_offer_params<'a>(params: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let mut config = Self {
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+            server_max_window_bits: 15,
+        };
+        let mut seen = Vec::new();
+
+        for param in params {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+
+            // 같은 파라미터가 두번 오면 그 offer는 거절해야 해요.
+            if seen.contains(&name) {
+                return None;
+            }
+            seen.push(name);
+
+            match (name, value) {
+                ("server_no_context_takeover", None) => config.server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => config.client_no_context_takeover = true,
+                // zlib은 raw deflate에서 8비트 윈도우를 못만들어요. 9부터 됩니다.
+                ("server_max_window_bits", Some(value)) => match value.parse() {
+                    Ok(bits @ 9..=15) => config.server_max_window_bits = bits,
+                    _ => return None,
+                },
+                /*
+                    클라이언트가 윈도우 크기를 줄일 수 있다는 뜻.
+                    우리는 항상 가장 큰 윈도우(15)로 풀기 때문에 어떤 크기로 압축해서 보내든 괜찮아요.
+                    그래서 응답에 따로 적지 않습니다.
+                */
+                ("client_max_window_bits", None) => {}
+                ("client_max_window_bits", Some(value)) => match value.parse::<u8>() {
+                    Ok(8..=15) => {}
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+
+        Some(config)
+    }
+
+    /// 101 응답의 `Sec-WebSocket-Extensions` 헤더 값.
+    pub fn response_header_value(&self) -> String {
+        let mut value = EXTENSION_NAME.to_string();
+        if self.server_no_context_takeover {
+            value.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            value.push_str("; client_no_context_takeover");
+        }
+        if self.server_max_window_bits != 15 {
+            value.push_str(&format!(
+                "; server_max_window_bits={}",
+                self.server_max_window_bits
+            ));
+        }
+        value
+    }
+}
+
+/// 보내는 메시지를 압축합니다. 연결마다 하나씩.
+pub struct Deflater {
+    compress: Compress,
+    no_context_takeover: bool,
+}
+
+impl Deflater {
+    /// `role`은 압축해서 보내는 쪽이 누구인지예요.
+    pub fn new(config: &DeflateConfig, role: Role) -> Self {
+        let (window_bits, no_context_takeover) = match role {
+            Role::Server => (
+                config.server_max_window_bits,
+                config.server_no_context_takeover,
+            ),
+            // 클라이언트의 윈도우 크기는 서버가 제한하지 않으니 항상 가장 큰 걸 써요.
+            Role::Client => (15, config.client_no_context_takeover),
+        };
+
+        Self {
+            compress: Compress::new_with_window_bits(Compression::default(), false, window_bits),
+            no_context_takeover,
+        }
+    }
+
+    pub fn compress(&mut self, payload: &[u8]) -> std::io::Result<Vec<u8>> {
+        if self.no_context_takeover {
+            self.compress.reset();
+        }
+
+        let mut output = Vec::with_capacity(payload.len() / 2 + 16);
+        let start_in = self.compress.total_in();
+
+        loop {
+            let consumed = (self.compress.total_in() - start_in) as usize;
+            output.reserve(1024);
+            self.compress
+                .compress_vec(&payload[consumed..], &mut output, FlushCompress::Sync)
+                .map_err(std::io::Error::other)?;
+
+            // 입력을 다 먹었고 출력 버퍼에 여유가 남았으면 flush까지 끝난거예요.
+            let consumed = (self.compress.total_in() - start_in) as usize;
+            if consumed == payload.len() && output.len() < output.capacity() {
+                break;
+            }
+        }
+
+        if output.ends_with(&DEFLATE_TAIL) {
+            output.truncate(output.len() - DEFLATE_TAIL.len());
+        }
+        // 빈 메시지는 빈 stored block 하나(0x00)로 보내야 해요. (RFC 7692 7.2.3.6)
+        if output.is_empty() {
+            output.push(0x00);
+        }
+
+        Ok(output)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecompressError {
+    /// 풀어보니 max_size보다 커요. 작게 보내고 크게 푸는 압축 폭탄일 수도 있어요.
+    TooBig,
+    /// 올바른 DEFLATE 데이터가 아니에요.
+    Invalid,
+}
+
+/// 받은 메시지의 압축을 풉니다. 연결마다 하나씩.
+pub struct Inflater {
+    decompress: Decompress,
+    no_context_takeover: bool,
+}
+
+impl Inflater {
+    /// `role`은 받아서 푸는 쪽이 누구인지예요.
+    pub fn new(config: &DeflateConfig, role: Role) -> Self {
+        // 상대가 사전을 매번 초기화한다면 우리도 똑같이 초기화해야 맞아떨어져요.
+        let no_context_takeover = match role {
+            Role::Server => config.client_no_context_takeover,
+            Role::Client => config.server_no_context_takeover,
+        };
+
+        Self {
+            decompress: Decompress::new_with_window_bits(false, 15),
+            no_context_takeover,
+        }
+    }
+
+    pub fn decompress(
+        &mut self,
+        payload: &[u8],
+        max_size: usize,
+    ) -> Result<Vec<u8>, DecompressError> {
+        if self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+
+        let mut input = Vec::with_capacity(payload.len() + DEFLATE_TAIL.len());
+        input.extend_from_slice(payload);
+        input.extend_from_slice(&DEFLATE_TAIL);
+
+        let mut output =
+            Vec::with_capacity((payload.len() * 2 + 16).min(max_size.saturating_add(1)));
+        let start_in = self.decompress.total_in();
+
+        loop {
+            let consumed = (self.decompress.total_in() - start_in) as usize;
+            output.reserve(1024);
+            let status = self
+                .decompress
+                .decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync)
+                .map_err(|_| DecompressError::Invalid)?;
+
+            if output.len() > max_size {
+                // 사전 상태가 어중간해졌으니 이 연결은 어차피 끊어야 해요.
+                return Err(DecompressError::TooBig);
+            }
+
+            // 클라이언트가 마지막 블록(BFINAL)으로 끝냈으면 다음 메시지는 새 스트림이에요.
+            if status == Status::StreamEnd {
+                self.decompress.reset(false);
+                break;
+            }
+
+            let consumed = (self.decompress.total_in() - start_in) as usize;
+            if consumed == input.len() && output.len() < output.capacity() {
+                break;
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn negotiate(value: &str) -> Option<DeflateConfig> {
+        DeflateConfig::negotiate(std::iter::once(value))
+    }
+
+    #[test]
+    fn test_from_response() {
+        assert_eq!(
+            DeflateConfig::from_response("permessage-deflate; server_no_context_takeover"),
+            Some(DeflateConfig {
+                server_no_context_takeover: true,
+                client_no_context_takeover: false,
+                server_max_window_bits: 15,
+            })
+        );
+        assert_eq!(
+            DeflateConfig::from_response("permessage-deflate; client_max_window_bits=10"),
+            None
+        );
+        assert_eq!(DeflateConfig::from_response("x-webkit-deflate-frame"), None);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(
+            negotiate("permessage-deflate; client_max_window_bits"),
+            Some(DeflateConfig {
+                server_no_context_takeover: false,
+                client_no_context_takeover: false,
+                server_max_window_bits: 15,
+            })
+        );
+        assert_eq!(negotiate("x-webkit-deflate-frame"), None);
+        // 첫 offer는 못받아들이니 두번째로 갑니다.
+        assert_eq!(
+            negotiate(
+                "permessage-deflate; server_max_window_bits=8, \
+                 permessage-deflate; server_no_context_takeover; server_max_window_bits=\"10\""
+            ),
+            Some(DeflateConfig {
+                server_no_context_takeover: true,
+                client_no_context_takeover: false,
+                server_max_window_bits: 10,
+            })
+        );
+        assert_eq!(negotiate("permessage-deflate; unknown_param"), None);
+        assert_eq!(
+            negotiate("permessage-deflate; client_no_context_takeover; client_no_context_takeover"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_response_header_value() {
+        let config =
+            negotiate("permessage-deflate; client_no_context_takeover; server_max_window_bits=12")
+                .unwrap();
+        assert_eq!(
+            config.response_header_value(),
+            "permessage-deflate; client_no_context_takeover; server_max_window_bits=12"
+        );
+    }
+
+    #[test]
+    fn test_decompress_rfc_7692_example() {
+        // RFC 7692 7.2.3.1: "Hello"를 압축한 결과
+        let mut inflater = Inflater::new(&negotiate("permessage-deflate").unwrap(), Role::Server);
+        let payload = inflater
+            .decompress(&[0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00], 1024)
+            .unwrap();
+        assert_eq!(payload, b"Hello");
+    }
+
+    #[test]
+    fn test_decompress_stops_at_max_size() {
+        let config = negotiate("permessage-deflate").unwrap();
+        let compressed = Deflater::new(&config, Role::Client)
+            .compress(&vec![0u8; 1024 * 1024])
+            .unwrap();
+        // 1MB짜리 0은 1KB 남짓으로 줄어들어요.
+        assert!(compressed.len() < 2 * 1024);
+
+        let mut inflater = Inflater::new(&config, Role::Server);
+        assert_eq!(
+            inflater.decompress(&compressed, 64 * 1024),
+            Err(DecompressError::TooBig)
+        );
+    }
+
+    #[test]
+    fn test_compress_round_trip_with_and_without_context_takeover() {
+        for header in [
+            "permessage-deflate",
+            "permessage-deflate; server_no_context_takeover; client_no_context_takeover",
+            "permessage-deflate; server_max_window_bits=9",
+        ] {
+            let config = negotiate(header).unwrap();
+            let mut deflater = Deflater::new(&config, Role::Server);
+            let mut inflater = Inflater::new(&config, Role::Client);
+
+            let long_message = "안녕하세요 ".repeat(10_000);
+            for message in ["Hello", "", long_message.as_str(), "Hello"] {
+                let compressed = deflater.compress(message.as_bytes()).unwrap();
+                assert_eq!(
+                    inflater.decompress(&compressed, usize::MAX).unwrap(),
+                    message.as_bytes()
+                );
+            }
+        }
+    }
+}
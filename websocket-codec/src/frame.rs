@@ -0,0 +1,248 @@
+// 0                   1                   2                   3
+// 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-------+-+-------------+-------------------------------+
+// |F|R|R|R| opcode|M| Payload len |    Extended payload length    |
+// |I|S|S|S|  (4)  |A|     (7)     |             (16/64)           |
+// |N|V|V|V|       |S|             |   (if payload len==126/127)   |
+// | |1|2|3|       |K|             |                               |
+// +-+-+-+-+-------+-+-------------+ - - - - - - - - - - - - - - - +
+// |     Extended payload length continued, if payload len == 127  |
+// + - - - - - - - - - - - - - - - +-------------------------------+
+// |                               |Masking-key, if MASK set to 1  |
+// +-------------------------------+-------------------------------+
+// | Masking-key (continued)       |          Payload Data         |
+// +-------------------------------- - - - - - - - - - - - - - - - +
+// :                     Payload Data continued ...                :
+// + - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - - +
+// |                     Payload Data continued ...                |
+// +---------------------------------------------------------------+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    /// 예약된 opcode(3~7, 11~15)면 None.
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Opcode::Continuation),
+            1 => Some(Opcode::Text),
+            2 => Some(Opcode::Binary),
+            8 => Some(Opcode::Close),
+            9 => Some(Opcode::Ping),
+            10 => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0,
+            Opcode::Text => 1,
+            Opcode::Binary => 2,
+            Opcode::Close => 8,
+            Opcode::Ping => 9,
+            Opcode::Pong => 10,
+        }
+    }
+
+    /// Close, Ping, Pong. 쪼갤 수 없고 payload는 125바이트까지.
+    pub fn is_control(self) -> bool {
+        self.as_u8() & 0b1000 != 0
+    }
+}
+
+/// 프레임 앞부분. payload 길이만 알고 payload는 아직 없어요.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub fin: bool,
+    pub rsv1: bool,
+    pub rsv2: bool,
+    pub rsv3: bool,
+    pub opcode: Opcode,
+    pub mask: Option<[u8; 4]>,
+    pub payload_length: u64,
+}
+
+impl FrameHeader {
+    /// 헤더를 바이트로. 최대 2 + 8 + 4 바이트예요.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(14);
+
+        header.push(
+            if self.fin { 0b1000_0000 } else { 0 }
+                | if self.rsv1 { 0b0100_0000 } else { 0 }
+                | if self.rsv2 { 0b0010_0000 } else { 0 }
+                | if self.rsv3 { 0b0001_0000 } else { 0 }
+                | self.opcode.as_u8(),
+        );
+
+        let mask_bit = if self.mask.is_some() { 0b1000_0000 } else { 0 };
+        match self.payload_length {
+            0..=125 => header.push(mask_bit | self.payload_length as u8),
+            126..=0xFFFF => {
+                header.push(mask_bit | 126);
+                header.extend_from_slice(&(self.payload_length as u16).to_be_bytes());
+            }
+            _ => {
+                header.push(mask_bit | 127);
+                header.extend_from_slice(&self.payload_length.to_be_bytes());
+            }
+        }
+
+        if let Some(mask) = self.mask {
+            header.extend_from_slice(&mask);
+        }
+
+        header
+    }
+}
+
+/// 마스킹은 XOR라서 한번 더 하면 원래대로 돌아와요. 씌울 때도 벗길 때도 이걸 씁니다.
+pub fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// 프레임 하나. payload는 항상 마스크를 벗긴 상태로 들고 있어요.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    pub rsv1: bool,
+    pub rsv2: bool,
+    pub rsv3: bool,
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// FIN이 켜진, 확장 비트 없는 프레임.
+    pub fn new(opcode: Opcode, payload: Vec<u8>) -> Self {
+        Self {
+            fin: true,
+            rsv1: false,
+            rsv2: false,
+            rsv3: false,
+            opcode,
+            payload,
+        }
+    }
+
+    pub fn header(&self, mask: Option<[u8; 4]>) -> FrameHeader {
+        FrameHeader {
+            fin: self.fin,
+            rsv1: self.rsv1,
+            rsv2: self.rsv2,
+            rsv3: self.rsv3,
+            opcode: self.opcode,
+            mask,
+            payload_length: self.payload.len() as u64,
+        }
+    }
+
+    /// 보낼 바이트. 클라이언트라면 `mask`를 꼭 넣어야 해요.
+    pub fn encode(&self, mask: Option<[u8; 4]>) -> Vec<u8> {
+        let mut bytes = self.header(mask).encode();
+        let payload_start = bytes.len();
+        bytes.extend_from_slice(&self.payload);
+        if let Some(mask) = mask {
+            apply_mask(&mut bytes[payload_start..], mask);
+        }
+        bytes
+    }
+}
+
+/*
+    너무 큰 메시지는 max_frame_size씩 잘라서 보냅니다.
+    첫 프레임만 원래 opcode이고, 나머지는 continuation(0). FIN은 마지막 프레임에만.
+    RSV1(압축 표시)도 opcode처럼 첫 프레임에만 켜요.
+    Text 메시지가 UTF-8 글자 중간에서 잘려도 괜찮아요. 받는 쪽은 다 합친 뒤에 UTF-8인지 봅니다.
+*/
+/// 메시지 하나를 보낼 프레임들로 나눕니다. 헤더는 마스크 없이 만들어요.
+pub fn fragment(
+    opcode: Opcode,
+    compressed: bool,
+    payload: &[u8],
+    max_frame_size: usize,
+) -> impl Iterator<Item = (FrameHeader, &[u8])> {
+    let max_frame_size = max_frame_size.max(1);
+    let chunk_count = payload.len().div_ceil(max_frame_size).max(1);
+
+    (0..chunk_count).map(move |index| {
+        let start = index * max_frame_size;
+        let end = (start + max_frame_size).min(payload.len());
+        let chunk = &payload[start..end];
+        let is_first = index == 0;
+
+        let header = FrameHeader {
+            fin: index == chunk_count - 1,
+            rsv1: is_first && compressed,
+            rsv2: false,
+            rsv3: false,
+            opcode: if is_first {
+                opcode
+            } else {
+                Opcode::Continuation
+            },
+            mask: None,
+            payload_length: chunk.len() as u64,
+        };
+        (header, chunk)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_extended_payload_lengths() {
+        let encode_header =
+            |length: usize| Frame::new(Opcode::Binary, vec![0; length]).encode(None);
+
+        assert_eq!(encode_header(125)[..2], [0x82, 125]);
+        assert_eq!(encode_header(126)[..4], [0x82, 126, 0x00, 126]);
+        assert_eq!(
+            encode_header(65536)[..10],
+            [0x82, 127, 0, 0, 0, 0, 0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_encode_masked_frame() {
+        // RFC 6455 5.7: 마스킹된 "Hello"
+        let frame = Frame::new(Opcode::Text, b"Hello".to_vec());
+        assert_eq!(
+            frame.encode(Some([0x37, 0xfa, 0x21, 0x3d])),
+            [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]
+        );
+    }
+
+    #[test]
+    fn test_fragment() {
+        let frames = fragment(Opcode::Text, true, b"abcde", 2)
+            .map(|(header, chunk)| (header.fin, header.rsv1, header.opcode, chunk))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            frames,
+            vec![
+                (false, true, Opcode::Text, &b"ab"[..]),
+                (false, false, Opcode::Continuation, &b"cd"[..]),
+                (true, false, Opcode::Continuation, &b"e"[..]),
+            ]
+        );
+
+        // 빈 메시지도 프레임 하나는 보내야 해요.
+        let frames = fragment(Opcode::Binary, false, b"", 2).collect::<Vec<_>>();
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].0.fin);
+    }
+}
@@ -0,0 +1,68 @@
+use base64::Engine;
+use sha1::Digest;
+
+/*
+    핸드셰이크에서 주고받는 키 (RFC 6455 4.1, 4.2.2)
+
+    클라이언트: Sec-WebSocket-Key: 랜덤 16바이트를 base64로
+    서버:       Sec-WebSocket-Accept: base64(sha1(key + GUID))
+
+    GUID는 모두가 똑같이 쓰는 고정된 값이에요.
+    서버가 정말 웹소켓을 아는지 (그냥 요청을 되돌려준 캐시 서버가 아닌지) 확인하는 용도입니다.
+*/
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// 우리가 아는 유일한 Sec-WebSocket-Version.
+pub const SUPPORTED_VERSION: &str = "13";
+
+/// 클라이언트가 보낼 Sec-WebSocket-Key. `nonce`는 연결마다 새로 뽑은 랜덤 바이트여야 해요.
+pub fn generate_key(nonce: [u8; 16]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(nonce)
+}
+
+/// Sec-WebSocket-Key가 base64로 인코딩된 16바이트인지.
+pub fn is_valid_key(key: &str) -> bool {
+    base64::engine::general_purpose::STANDARD
+        .decode(key)
+        .is_ok_and(|decoded| decoded.len() == 16)
+}
+
+/// 서버가 돌려줄 Sec-WebSocket-Accept.
+pub fn generate_accept_key(client_key: &str) -> String {
+    let mut hasher = sha1::Sha1::new();
+    hasher.update(client_key);
+    hasher.update(GUID);
+    let result = hasher.finalize();
+
+    base64::engine::general_purpose::STANDARD.encode(result)
+}
+
+/// 클라이언트 쪽에서, 서버가 돌려준 Sec-WebSocket-Accept가 맞는지.
+pub fn verify_accept_key(client_key: &str, accept_key: &str) -> bool {
+    generate_accept_key(client_key) == accept_key.trim()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generate_accept_key() {
+        let client_key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let accept_key = generate_accept_key(client_key);
+        assert_eq!(accept_key, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_key_round_trip() {
+        let key = generate_key(*b"the sample nonce");
+        assert_eq!(key, "dGhlIHNhbXBsZSBub25jZQ==");
+        assert!(is_valid_key(&key));
+        assert!(!is_valid_key("not base64!"));
+        assert!(!is_valid_key("c2hvcnQ="));
+
+        assert!(verify_accept_key(&key, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+        assert!(!verify_accept_key(&key, "wrong"));
+    }
+}
@@ -0,0 +1,35 @@
+/*!
+소켓 없이 바이트만 가지고 하는 WebSocket(RFC 6455).
+
+- 받은 바이트를 [`Decoder`]에 넣으면 [`Frame`]이 나오고
+- [`MessageAssembler`]에 프레임을 넣으면 쪼개진 조각을 합쳐서 [`Message`]가 나오고
+- 보낼 땐 [`Frame::encode`]나 [`fragment`]로 바이트를 만듭니다.
+
+읽고 쓰는 건 부르는 쪽이 알아서 해요. 그래서 서버에도, 클라이언트에도, 테스트에도 똑같이 씁니다.
+
+`client` feature를 켜면 이걸로 만든 tokio 클라이언트([`client::connect`])도 쓸 수 있어요.
+*/
+
+#[cfg(feature = "client")]
+pub mod client;
+pub mod close;
+mod decoder;
+pub mod deflate;
+mod frame;
+pub mod handshake;
+mod message;
+pub mod utf8;
+
+pub use close::{CloseCode, CloseFrame};
+pub use decoder::{DecodeError, Decoder};
+pub use frame::{apply_mask, fragment, Frame, FrameHeader, Opcode};
+pub use message::{AssembleError, Message, MessageAssembler};
+
+/// 연결에서 내가 어느 쪽인지.
+///
+/// 클라이언트가 보내는 프레임은 항상 마스킹되고, 서버가 보내는 프레임은 절대 마스킹되지 않아요.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Server,
+    Client,
+}
@@ -0,0 +1,289 @@
+use crate::{
+    close::CloseCode,
+    deflate::{DecompressError, Inflater},
+    frame::{Frame, Opcode},
+    utf8::Utf8Validator,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/*
+    메시지 하나가 여러 프레임으로 쪼개져서 올 수 있어요.
+    - 첫 프레임: opcode 1(Text) 또는 2(Binary), FIN 0
+    - 중간 프레임들: opcode 0 (continuation), FIN 0
+    - 마지막 프레임: opcode 0, FIN 1
+    그 사이사이에 Close 같은 control frame이 끼어들 수도 있구요.
+
+    control frame은 부르는 쪽에서 처리하고, 데이터 프레임만 여기에 넣어주세요.
+*/
+pub struct MessageAssembler {
+    inflater: Option<Inflater>,
+    max_message_size: usize,
+    /*
+        FIN이 꺼진 프레임들을 여기에 모아둡니다.
+        Some이면 "메시지를 받는 중", None이면 "새 메시지를 기다리는 중".
+    */
+    fragments: Option<FragmentedMessage>,
+}
+
+struct FragmentedMessage {
+    /// 첫 프레임의 opcode. Text인지 Binary인지.
+    opcode: Opcode,
+    /// 첫 프레임에 RSV1이 켜져 있었으면 압축된 메시지예요.
+    compressed: bool,
+    /// 지금까지 모은 payload.
+    payload: Vec<u8>,
+    /// 압축되지 않은 Text 메시지면 조각이 올 때마다 UTF-8인지 검사해요.
+    /// 압축된 메시지는 다 모아서 풀어봐야 알 수 있으니 None.
+    utf8_validator: Option<Utf8Validator>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AssembleError {
+    /// 프레임 순서가 맞지 않아요.
+    Protocol(&'static str),
+    /// Text 메시지가 UTF-8이 아니에요.
+    InvalidUtf8,
+    /// 압축을 풀 수 없어요.
+    InvalidCompressedData,
+    /// 설정한 크기 제한을 넘었어요.
+    MessageTooBig { max_message_size: usize },
+}
+
+impl std::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssembleError::Protocol(reason) => write!(f, "{reason}"),
+            AssembleError::InvalidUtf8 => write!(f, "Text message is not valid UTF-8"),
+            AssembleError::InvalidCompressedData => write!(f, "Fail to decompress message"),
+            AssembleError::MessageTooBig { max_message_size } => {
+                write!(
+                    f,
+                    "Message must not be larger than {max_message_size} bytes"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+impl AssembleError {
+    /// 이 에러로 연결을 닫을 때 Close frame에 담을 코드.
+    pub fn close_code(&self) -> CloseCode {
+        match self {
+            AssembleError::Protocol(_) => CloseCode::PROTOCOL_ERROR,
+            AssembleError::InvalidUtf8 | AssembleError::InvalidCompressedData => {
+                CloseCode::INVALID_DATA
+            }
+            AssembleError::MessageTooBig { .. } => CloseCode::MESSAGE_TOO_BIG,
+        }
+    }
+}
+
+impl MessageAssembler {
+    /// 압축 상태는 연결마다 따로 가지고 있어야 해요. (context takeover)
+    pub fn new(inflater: Option<Inflater>, max_message_size: usize) -> Self {
+        Self {
+            inflater,
+            max_message_size,
+            fragments: None,
+        }
+    }
+
+    /// 데이터 프레임을 하나 넣습니다. 메시지가 완성되면 Some.
+    pub fn push(&mut self, frame: Frame) -> Result<Option<Message>, AssembleError> {
+        let mut fragmented_message = match (frame.opcode, self.fragments.take()) {
+            (Opcode::Continuation, Some(fragmented_message)) => fragmented_message,
+            (Opcode::Continuation, None) => {
+                return Err(AssembleError::Protocol(
+                    "Continuation frame without a message to continue",
+                ));
+            }
+            (Opcode::Text | Opcode::Binary, None) => FragmentedMessage {
+                opcode: frame.opcode,
+                compressed: frame.rsv1,
+                payload: Vec::new(),
+                utf8_validator: (frame.opcode == Opcode::Text && !frame.rsv1)
+                    .then(Utf8Validator::new),
+            },
+            (Opcode::Text | Opcode::Binary, Some(_)) => {
+                return Err(AssembleError::Protocol(
+                    "New message started before the fragmented message finished",
+                ));
+            }
+            (Opcode::Close | Opcode::Ping | Opcode::Pong, _) => {
+                return Err(AssembleError::Protocol(
+                    "Control frames are not part of a message",
+                ));
+            }
+        };
+
+        if fragmented_message.payload.len() + frame.payload.len() > self.max_message_size {
+            return Err(AssembleError::MessageTooBig {
+                max_message_size: self.max_message_size,
+            });
+        }
+
+        // 이상한 바이트가 보이면 나머지 조각을 기다리지 않고 바로 끊습니다.
+        if let Some(utf8_validator) = &mut fragmented_message.utf8_validator {
+            utf8_validator
+                .feed(&frame.payload)
+                .map_err(|_| AssembleError::InvalidUtf8)?;
+        }
+        if fragmented_message.payload.is_empty() {
+            fragmented_message.payload = frame.payload;
+        } else {
+            fragmented_message.payload.extend_from_slice(&frame.payload);
+        }
+
+        if !frame.fin {
+            // 아직 마지막 조각이 안왔어요. 모아두고 다음 프레임을 기다립시다.
+            self.fragments = Some(fragmented_message);
+            return Ok(None);
+        }
+
+        let FragmentedMessage {
+            opcode,
+            compressed,
+            mut payload,
+            utf8_validator,
+        } = fragmented_message;
+
+        // 마지막 글자가 중간에 끊긴 채로 끝났는지도 봐야 해요.
+        if let Some(utf8_validator) = utf8_validator {
+            utf8_validator
+                .finish()
+                .map_err(|_| AssembleError::InvalidUtf8)?;
+        }
+
+        if compressed {
+            let inflater = self.inflater.as_mut().ok_or(AssembleError::Protocol(
+                "Compressed message without permessage-deflate",
+            ))?;
+            payload = inflater
+                .decompress(&payload, self.max_message_size)
+                .map_err(|error| match error {
+                    DecompressError::TooBig => AssembleError::MessageTooBig {
+                        max_message_size: self.max_message_size,
+                    },
+                    DecompressError::Invalid => AssembleError::InvalidCompressedData,
+                })?;
+        }
+
+        let message = match opcode {
+            // 압축된 메시지는 여기서 처음 검사하게 돼요.
+            Opcode::Text => {
+                Message::Text(String::from_utf8(payload).map_err(|_| AssembleError::InvalidUtf8)?)
+            }
+            _ => Message::Binary(payload),
+        };
+
+        Ok(Some(message))
+    }
+
+    /// 쪼개진 메시지를 받는 중인지.
+    pub fn is_receiving(&self) -> bool {
+        self.fragments.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        deflate::{DeflateConfig, Deflater},
+        Role,
+    };
+
+    fn data_frame(opcode: Opcode, fin: bool, payload: &[u8]) -> Frame {
+        Frame {
+            fin,
+            ..Frame::new(opcode, payload.to_vec())
+        }
+    }
+
+    #[test]
+    fn test_assemble_fragmented_text_message() {
+        let mut assembler = MessageAssembler::new(None, 1024);
+        let text = "안녕".as_bytes();
+
+        // 글자 중간에서 잘려도 괜찮아요.
+        assert_eq!(
+            assembler.push(data_frame(Opcode::Text, false, &text[..2])),
+            Ok(None)
+        );
+        assert!(assembler.is_receiving());
+        assert_eq!(
+            assembler.push(data_frame(Opcode::Continuation, true, &text[2..])),
+            Ok(Some(Message::Text("안녕".to_string())))
+        );
+        assert!(!assembler.is_receiving());
+    }
+
+    #[test]
+    fn test_assemble_rejects_bad_sequences() {
+        let mut assembler = MessageAssembler::new(None, 1024);
+        assert!(matches!(
+            assembler.push(data_frame(Opcode::Continuation, true, b"")),
+            Err(AssembleError::Protocol(_))
+        ));
+
+        let mut assembler = MessageAssembler::new(None, 1024);
+        assembler
+            .push(data_frame(Opcode::Binary, false, b"a"))
+            .unwrap();
+        assert!(matches!(
+            assembler.push(data_frame(Opcode::Text, true, b"b")),
+            Err(AssembleError::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn test_assemble_rejects_invalid_utf8_early() {
+        let mut assembler = MessageAssembler::new(None, 1024);
+        assert_eq!(
+            assembler.push(data_frame(Opcode::Text, false, &[0xff])),
+            Err(AssembleError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn test_assemble_enforces_message_size() {
+        let mut assembler = MessageAssembler::new(None, 4);
+        assembler
+            .push(data_frame(Opcode::Binary, false, b"abc"))
+            .unwrap();
+        assert_eq!(
+            assembler.push(data_frame(Opcode::Continuation, true, b"de")),
+            Err(AssembleError::MessageTooBig {
+                max_message_size: 4
+            })
+        );
+    }
+
+    #[test]
+    fn test_assemble_compressed_message() {
+        let config = DeflateConfig {
+            server_no_context_takeover: false,
+            client_no_context_takeover: false,
+            server_max_window_bits: 15,
+        };
+        let mut deflater = Deflater::new(&config, Role::Client);
+        let mut assembler = MessageAssembler::new(Some(Inflater::new(&config, Role::Server)), 1024);
+
+        let compressed = deflater.compress(b"hello hello").unwrap();
+        let frame = Frame {
+            rsv1: true,
+            ..Frame::new(Opcode::Text, compressed)
+        };
+        assert_eq!(
+            assembler.push(frame),
+            Ok(Some(Message::Text("hello hello".to_string())))
+        );
+    }
+}
@@ -0,0 +1,122 @@
+/*
+    쪼개진 Text 메시지를 조각이 올 때마다 UTF-8인지 검사합니다.
+
+    다 모은 다음에 한번에 검사하면 간단하지만,
+    - 첫 조각부터 이미 틀렸는데 나머지 몇 MB를 다 받고 나서야 알게 되고
+    - 글자 하나(최대 4바이트)가 두 조각에 걸쳐서 올 수도 있어요.
+    그래서 조각 끝에 걸친 "아직 덜 온 글자"만 기억해뒀다가 다음 조각과 이어서 봅니다.
+*/
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidUtf8;
+
+pub struct Utf8Validator {
+    /// 이전 조각 끝에 걸쳐있던, 아직 완성되지 않은 글자의 앞부분. (최대 3바이트)
+    incomplete: Vec<u8>,
+}
+
+impl Default for Utf8Validator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Utf8Validator {
+    pub fn new() -> Self {
+        Self {
+            incomplete: Vec::with_capacity(4),
+        }
+    }
+
+    /// 이어서 들어온 바이트들을 검사합니다.
+    /// 뒤에 뭐가 오든 UTF-8이 될 수 없으면 바로 Err.
+    pub fn feed(&mut self, mut bytes: &[u8]) -> Result<(), InvalidUtf8> {
+        if !self.incomplete.is_empty() {
+            // 덜 온 글자를 먼저 완성시켜 봅니다. 글자 하나는 4바이트를 넘지 않아요.
+            let take = bytes.len().min(4 - self.incomplete.len());
+            let mut combined = self.incomplete.clone();
+            combined.extend_from_slice(&bytes[..take]);
+
+            let consumed = match std::str::from_utf8(&combined) {
+                Ok(_) => take,
+                Err(error) if error.valid_up_to() > 0 => {
+                    error.valid_up_to() - self.incomplete.len()
+                }
+                Err(error) if error.error_len().is_some() => return Err(InvalidUtf8),
+                // 이번 조각을 다 붙여도 아직 글자가 안끝났어요.
+                Err(_) => {
+                    self.incomplete = combined;
+                    return Ok(());
+                }
+            };
+
+            self.incomplete.clear();
+            bytes = &bytes[consumed..];
+        }
+
+        match std::str::from_utf8(bytes) {
+            Ok(_) => Ok(()),
+            Err(error) => match error.error_len() {
+                Some(_) => Err(InvalidUtf8),
+                // 끝이 잘린 것뿐이라면 다음 조각을 기다립니다.
+                None => {
+                    self.incomplete
+                        .extend_from_slice(&bytes[error.valid_up_to()..]);
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    /// 메시지의 마지막 조각까지 받았을 때. 글자가 중간에 끝났으면 Err.
+    pub fn finish(&self) -> Result<(), InvalidUtf8> {
+        if self.incomplete.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidUtf8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn validate(chunks: &[&[u8]]) -> Result<(), InvalidUtf8> {
+        let mut validator = Utf8Validator::new();
+        for chunk in chunks {
+            validator.feed(chunk)?;
+        }
+        validator.finish()
+    }
+
+    #[test]
+    fn test_code_point_split_across_chunks() {
+        // "가" = EA B0 80, "😀" = F0 9F 98 80
+        assert_eq!(validate(&[b"a\xEA", b"\xB0\x80b"]), Ok(()));
+        assert_eq!(validate(&[b"\xF0", b"\x9F", b"", b"\x98", b"\x80"]), Ok(()));
+        assert_eq!(
+            validate(&[b"\xF0\x9F", b"\x98\x80\xEA\xB0", b"\x80"]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_invalid_sequence_is_rejected_without_waiting_for_the_rest() {
+        let mut validator = Utf8Validator::new();
+        // U+10FFFF보다 큰 값이 될 수밖에 없어요.
+        assert_eq!(validator.feed(b"ok\xF4\x90"), Err(InvalidUtf8));
+
+        let mut validator = Utf8Validator::new();
+        assert_eq!(validator.feed(b"\xED"), Ok(()));
+        // 서로게이트(U+D800)는 UTF-8이 아니에요.
+        assert_eq!(validator.feed(b"\xA0"), Err(InvalidUtf8));
+
+        assert_eq!(validate(&[b"\xF0\x9F", b"\x98a"]), Err(InvalidUtf8));
+    }
+
+    #[test]
+    fn test_message_must_not_end_in_the_middle_of_a_code_point() {
+        assert_eq!(validate(&[b"a\xEA\xB0"]), Err(InvalidUtf8));
+    }
+}
@@ -0,0 +1,114 @@
+/*
+    유저 목록 벤치마크: Hub(여러 조각으로 나눈 목록) vs 예전 모양(lock 하나 + Vec)
+
+    cargo bench --bench hub --features bench
+
+    유저 수마다
+    - join: 전부 들어오는 데 걸린 시간
+    - broadcast: 메시지 하나를 모두에게 뿌리는 데 걸린 시간
+    - churn: 한 스레드가 계속 뿌리는 동안, 다른 스레드들이 들어왔다 나가기를 한 번 하는 데 걸린 평균 시간
+      서버에서도 한 방의 메시지는 한 번에 하나씩 뿌려요. (Membership::lock_message_order) 그러니 뿌리는 스레드는 하나예요.
+    - leave: 전부 나가는 데 걸린 시간
+*/
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use websocket_server::bench::{GlobalVec, ShardedHub, UserList};
+
+const USER_COUNTS: &[u64] = &[1_000, 10_000, 50_000];
+const BROADCAST_COUNT: i64 = 100;
+const CHURN_THREADS: u64 = 4;
+const CHURN_ROUNDS: u64 = 200;
+
+struct Timings {
+    join: Duration,
+    broadcast: Duration,
+    churn: Duration,
+    leave: Duration,
+}
+
+fn run(user_list: Arc<dyn UserList>, user_count: u64) -> Timings {
+    let started = Instant::now();
+    for id in 0..user_count {
+        user_list.join(id);
+    }
+    let join = started.elapsed();
+
+    let started = Instant::now();
+    for message_id in 0..BROADCAST_COUNT {
+        user_list.broadcast(0, message_id);
+    }
+    let broadcast = started.elapsed() / BROADCAST_COUNT as u32;
+
+    // 새로 들어오는 유저의 id는 겹치지 않게 뒤쪽을 써요.
+    let broadcasting = Arc::new(AtomicBool::new(true));
+    let broadcaster = {
+        let user_list = user_list.clone();
+        let broadcasting = broadcasting.clone();
+        thread::spawn(move || {
+            let mut message_id = BROADCAST_COUNT;
+            while broadcasting.load(Ordering::Relaxed) {
+                user_list.broadcast(0, message_id);
+                message_id += 1;
+            }
+        })
+    };
+    let started = Instant::now();
+    let churners: Vec<_> = (0..CHURN_THREADS)
+        .map(|thread_index| {
+            let user_list = user_list.clone();
+            thread::spawn(move || {
+                for round in 0..CHURN_ROUNDS {
+                    let id = user_count + thread_index * CHURN_ROUNDS + round;
+                    user_list.join(id);
+                    user_list.leave(id);
+                }
+            })
+        })
+        .collect();
+    for churner in churners {
+        churner.join().unwrap();
+    }
+    let churn = started.elapsed() / CHURN_ROUNDS as u32;
+    broadcasting.store(false, Ordering::Relaxed);
+    broadcaster.join().unwrap();
+
+    let started = Instant::now();
+    for id in 0..user_count {
+        user_list.leave(id);
+    }
+    let leave = started.elapsed();
+
+    Timings {
+        join,
+        broadcast,
+        churn,
+        leave,
+    }
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>12} {:>12} {:>14} {:>12} {:>12}",
+        "users", "list", "join all", "one broadcast", "join+leave", "leave all"
+    );
+    for &user_count in USER_COUNTS {
+        let user_lists: [(&str, Arc<dyn UserList>); 2] = [
+            ("hub", Arc::new(ShardedHub::new())),
+            ("vec", Arc::new(GlobalVec::new())),
+        ];
+        for (name, user_list) in user_lists {
+            let timings = run(user_list, user_count);
+            println!(
+                "{:>8} {:>12} {:>12.2?} {:>14.2?} {:>12.2?} {:>12.2?}",
+                user_count, name, timings.join, timings.broadcast, timings.churn, timings.leave
+            );
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{
+    config::SlowConsumerPolicy,
+    hub::Hub,
+    outbox::{Delivery, Outbox, Pushed},
+};
+use std::sync::{Arc, Mutex};
+use websocket_codec::Message;
+
+/*
+    benches/hub.rs에서만 써요. 서버를 띄우지 않고 유저 목록만 따로 재볼 수 있게 꺼내둡니다.
+
+    비교 대상은 Hub 전의 모양이에요. lock 하나에 Vec 하나, 나갈 때는 retain.
+    lock 종류만 std Mutex로 바꿨어요. 비교하려는 건 lock 하나를 모두가 잡는 것과 Vec을 훑는 거니까요.
+*/
+
+/// 아무도 꺼내 가지 않으니 유저마다 이만큼만 쌓이고, 그 뒤로는 가장 오래된 걸 버려요.
+const OUTBOX_CAPACITY: usize = 16;
+
+pub trait UserList: Send + Sync {
+    fn join(&self, id: u64);
+    fn leave(&self, id: u64);
+    fn broadcast(&self, from: u64, message_id: i64);
+}
+
+fn new_outbox() -> Arc<Outbox> {
+    Arc::new(Outbox::new(OUTBOX_CAPACITY, SlowConsumerPolicy::DropOldest))
+}
+
+fn delivery(message_id: i64) -> Delivery {
+    Delivery {
+        id: message_id,
+        author: None,
+        timestamp: None,
+        message: Message::Text("hello".to_string()),
+    }
+}
+
+pub struct ShardedHub(Hub);
+
+impl ShardedHub {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(Hub::new())
+    }
+}
+
+impl UserList for ShardedHub {
+    fn join(&self, id: u64) {
+        self.0.join(id, new_outbox());
+    }
+
+    fn leave(&self, id: u64) {
+        self.0.leave(id);
+    }
+
+    fn broadcast(&self, from: u64, message_id: i64) {
+        self.0.broadcast(&delivery(message_id), from);
+    }
+}
+
+pub struct GlobalVec(Mutex<Vec<(u64, Arc<Outbox>)>>);
+
+impl GlobalVec {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(Mutex::new(vec![]))
+    }
+}
+
+impl UserList for GlobalVec {
+    fn join(&self, id: u64) {
+        self.0.lock().unwrap().push((id, new_outbox()));
+    }
+
+    fn leave(&self, id: u64) {
+        self.0.lock().unwrap().retain(|(user_id, _)| *user_id != id);
+    }
+
+    fn broadcast(&self, from: u64, message_id: i64) {
+        let delivery = delivery(message_id);
+        self.0.lock().unwrap().retain(|(id, outbox)| {
+            *id == from || !matches!(outbox.push(delivery.clone()), Pushed::Disconnected)
+        });
+    }
+}
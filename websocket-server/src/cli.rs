@@ -0,0 +1,276 @@
+use crate::config::Config;
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+/// main이 할 일.
+pub enum Command {
+    Serve(Config),
+    /// 합쳐진 설정을 TOML로 보여주고 끝나요. 설정이 어떻게 읽혔는지 확인할 때 써요.
+    PrintConfig(Config),
+    Help,
+}
+
+pub const USAGE: &str = "\
+Usage: websocket-server [OPTIONS]
+
+Options:
+  --config <PATH>      Read settings from a TOML file
+  --print-config       Print the merged settings as TOML and exit
+  --<KEY> <VALUE>      Override a setting, e.g. --bind-address 127.0.0.1:9000
+                       Nested keys use dots: --http-request-limits.max-header-size 8192
+  -h, --help           Print this help
+
+Every setting can also be set with an environment variable:
+  WEBSOCKET_SERVER_CONFIG=<PATH>, WEBSOCKET_SERVER_BIND_ADDRESS=127.0.0.1:9000,
+  WEBSOCKET_SERVER_TLS__CERTIFICATE_PATH=cert.pem (`__` for nested keys)
+
+Values are TOML values (30, 0.5, true, [\"https://example.com\"]).
+Anything that does not parse as one is taken as a string.
+";
+
+const ENV_PREFIX: &str = "WEBSOCKET_SERVER_";
+
+/*
+    설정은 네 군데서 와요. 뒤에 있는 게 앞에 있는 걸 덮어씁니다.
+    1. Config::default()
+    2. 설정 파일 (--config, WEBSOCKET_SERVER_CONFIG)
+    3. 환경변수 (WEBSOCKET_SERVER_*)
+    4. 명령줄 (--key value)
+
+    하나하나 Config 필드에 넣으면 필드가 늘 때마다 여기도 고쳐야 하니,
+    전부 TOML 테이블 하나에 합친 뒤에 마지막에 한번만 Config로 바꿔요.
+    그러면 모르는 키나 타입이 틀린 값은 파일에서 왔든 명령줄에서 왔든 똑같이 걸러집니다.
+*/
+/// `args`에는 프로그램 이름을 빼고 넣어주세요. `env`는 테스트에서 바꿔 넣을 수 있게 받아요.
+pub fn parse_command(
+    args: impl IntoIterator<Item = String>,
+    env: impl IntoIterator<Item = (String, String)>,
+) -> Result<Command> {
+    let mut config_path = None;
+    let mut print_config = false;
+    let mut cli_overrides = vec![];
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let flag = match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--print-config" => {
+                print_config = true;
+                continue;
+            }
+            _ => arg
+                .strip_prefix("--")
+                .ok_or_else(|| anyhow!("Unexpected argument: {arg}\n\n{USAGE}"))?,
+        };
+
+        // --key value, --key=value 둘 다 돼요.
+        let (key, value) = match flag.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("Missing value for --{flag}"))?;
+                (flag.to_string(), value)
+            }
+        };
+
+        if key == "config" {
+            config_path = Some(PathBuf::from(value));
+        } else {
+            cli_overrides.push((key.replace('-', "_"), value));
+        }
+    }
+
+    let mut env_overrides = vec![];
+    for (name, value) in env {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if key == "CONFIG" {
+            // 명령줄의 --config가 우선이에요.
+            config_path.get_or_insert(PathBuf::from(value));
+        } else {
+            env_overrides.push((key.to_ascii_lowercase().replace("__", "."), value));
+        }
+    }
+
+    let mut table = match &config_path {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Fail to read {}", path.display()))?
+            .parse::<Table>()
+            .with_context(|| format!("Invalid TOML in {}", path.display()))?,
+        None => Table::new(),
+    };
+    for (key, value) in env_overrides.into_iter().chain(cli_overrides) {
+        set_value(&mut table, &key, parse_value(&value))?;
+    }
+
+    let config: Config = Value::Table(table)
+        .try_into()
+        .context("Invalid configuration")?;
+    config.validate().context("Invalid configuration")?;
+
+    Ok(if print_config {
+        Command::PrintConfig(config)
+    } else {
+        Command::Serve(config)
+    })
+}
+
+/// `30`, `true`, `["a", "b"]`처럼 TOML 값으로 읽히면 그 값, 아니면 문자열.
+fn parse_value(raw: &str) -> Value {
+    format!("value = {raw}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+/// `http_request_limits.max_header_size`처럼 점으로 이어진 키에 값을 넣어요. 중간 테이블이 없으면 만들어요.
+fn set_value(table: &mut Table, key: &str, value: Value) -> Result<()> {
+    let mut parts = key.split('.').collect::<Vec<_>>();
+    let last = parts.pop().unwrap();
+
+    let mut table = table;
+    for part in parts {
+        table = match table
+            .entry(part)
+            .or_insert_with(|| Value::Table(Table::new()))
+        {
+            Value::Table(table) => table,
+            _ => bail!("{part} in {key} is not a table"),
+        };
+    }
+    table.insert(last.to_string(), value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::SlowConsumerPolicy;
+    use std::time::Duration;
+
+    fn parse(args: &[&str], env: &[(&str, &str)]) -> Result<Command> {
+        parse_command(
+            args.iter().map(|arg| arg.to_string()),
+            env.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        )
+    }
+
+    fn serve_config(args: &[&str], env: &[(&str, &str)]) -> Config {
+        match parse(args, env).unwrap() {
+            Command::Serve(config) => config,
+            _ => panic!("expected Serve"),
+        }
+    }
+
+    #[test]
+    fn test_later_sources_override_earlier_ones() {
+        let path = std::env::temp_dir().join(format!(
+            "websocket-server-config-test-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            "bind_address = \"127.0.0.1:9000\"\n\
+             history_size = 20\n\
+             ping_interval = 1.5\n\
+             [http_request_limits]\n\
+             max_header_count = 8\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = serve_config(
+            &["--config", path, "--history-size=50"],
+            &[
+                ("WEBSOCKET_SERVER_HISTORY_SIZE", "30"),
+                ("WEBSOCKET_SERVER_DATABASE_URL", "sqlite:other.sqlite"),
+                (
+                    "WEBSOCKET_SERVER_HTTP_REQUEST_LIMITS__MAX_HEADER_SIZE",
+                    "1024",
+                ),
+                ("UNRELATED", "ignored"),
+            ],
+        );
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(config.bind_address, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(config.history_size, 50);
+        assert_eq!(config.database_url, "sqlite:other.sqlite");
+        assert_eq!(config.ping_interval, Duration::from_millis(1500));
+        assert_eq!(config.http_request_limits.max_header_count, 8);
+        assert_eq!(config.http_request_limits.max_header_size, 1024);
+        // 적지 않은 값은 기본값
+        assert_eq!(config.channel_capacity, 1024);
+    }
+
+    #[test]
+    fn test_nested_and_list_values() {
+        let config = serve_config(
+            &[
+                "--tls.certificate-path",
+                "cert.pem",
+                "--tls.private-key-path",
+                "key.pem",
+                "--allowed-origins",
+                "[\"https://chat.example.com\"]",
+                "--slow-consumer-policy",
+                "replay-from-db",
+            ],
+            &[],
+        );
+        assert_eq!(
+            config.tls.unwrap().certificate_path,
+            PathBuf::from("cert.pem")
+        );
+        assert_eq!(config.allowed_origins, vec!["https://chat.example.com"]);
+        assert_eq!(
+            config.slow_consumer_policy,
+            SlowConsumerPolicy::ReplayFromDb
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_configuration() {
+        for args in [
+            &["--no-such-setting", "1"][..],
+            &["--channel-capacity", "many"],
+            &["--channel-capacity", "0"],
+            &["--slow-consumer-policy", "block"],
+            &["--ping-interval", "-1"],
+            &["--bind-address", "not an address"],
+            &["--history-size"],
+            &["positional"],
+            &["--config", "/nonexistent/config.toml"],
+        ] {
+            assert!(parse(args, &[]).is_err(), "{args:?}");
+        }
+        assert!(parse(&[], &[("WEBSOCKET_SERVER_TYPO", "1")]).is_err());
+    }
+
+    #[test]
+    fn test_print_config_round_trips() {
+        let Command::PrintConfig(config) =
+            parse(&["--print-config", "--history-size", "5"], &[]).unwrap()
+        else {
+            panic!("expected PrintConfig");
+        };
+        let printed = config.to_toml();
+
+        let path = std::env::temp_dir().join(format!(
+            "websocket-server-print-config-test-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, &printed).unwrap();
+        let config = serve_config(&["--config", path.to_str().unwrap()], &[]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.history_size, 5);
+        assert_eq!(config.to_toml(), printed);
+    }
+}
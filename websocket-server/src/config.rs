@@ -0,0 +1,210 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+/*
+    서버 동작을 조절하는 값들.
+
+    설정 파일(TOML)의 키 이름은 필드 이름과 같아요. 적지 않은 값은 Default를 씁니다.
+    시간은 초 단위 숫자로 적어요. (`ping_interval = 30`, `close_timeout = 0.5`)
+    파일, 환경변수, 명령줄에서 읽어서 합치는 건 cli.rs에 있어요.
+*/
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 여기서 연결을 기다려요. 여러 서버를 띄우려면 포트를 다르게 주세요.
+    pub bind_address: SocketAddr,
+    /// sqlx가 알아듣는 SQLite 주소. 서버마다 다른 파일을 쓰게 할 수 있어요.
+    pub database_url: String,
+    /// 유저마다 아직 못 보낸 메시지를 이만큼까지 쌓아둬요.
+    pub channel_capacity: usize,
+    /// 쌓아둔 게 channel_capacity만큼 차버린 느린 유저를 어떻게 할지.
+    pub slow_consumer_policy: SlowConsumerPolicy,
+    /// 첫 페이지에 보여줄 최근 메시지 개수.
+    pub history_size: usize,
+    /// 이 간격마다 서버가 먼저 Ping을 보냅니다.
+    #[serde(with = "seconds")]
+    pub ping_interval: Duration,
+    /// Ping을 보낸 뒤 이 시간 안에 Pong이 안오면 죽은 연결로 보고 끊습니다.
+    #[serde(with = "seconds")]
+    pub pong_timeout: Duration,
+    /// Close를 주고받은 뒤 상대가 TCP를 닫아주길 기다리는 최대 시간.
+    #[serde(with = "seconds")]
+    pub close_timeout: Duration,
+    /// 서버를 끌 때 유저들의 연결이 정리되길 기다리는 최대 시간. 넘으면 그냥 끊어요.
+    #[serde(with = "seconds")]
+    pub shutdown_timeout: Duration,
+    /// 이것보다 긴 메시지는 여러 프레임으로 쪼개서 보냅니다.
+    pub max_outbound_frame_size: usize,
+    /// 클라이언트가 보내는 프레임 하나의 최대 크기. 넘으면 1009로 끊어요.
+    pub max_inbound_frame_size: usize,
+    /// 조각들을 합치고 압축을 푼 메시지 하나의 최대 크기. 넘으면 1009로 끊어요.
+    pub max_inbound_message_size: usize,
+    /// 메시지의 첫 바이트가 오고 나서 마지막 조각까지 다 오길 기다리는 최대 시간. 넘으면 1008로 끊어요.
+    #[serde(with = "seconds")]
+    pub message_read_timeout: Duration,
+    /// 클라이언트가 원하면 permessage-deflate 압축을 쓸지.
+    pub permessage_deflate: bool,
+    pub http_request_limits: HttpRequestLimits,
+    /// 웹소켓 연결과 CORS를 허락할 다른 사이트들. `https://chat.example.com`처럼 적어요.
+    /// 우리가 준 페이지(Origin이 Host와 같음)와 Origin을 보내지 않는 클라이언트는 적지 않아도 허락해요.
+    /// `*`를 넣으면 전부 허락합니다.
+    pub allowed_origins: Vec<String>,
+    /// 있으면 TLS로만 받아요. (https://, wss://)
+    pub tls: Option<TlsConfig>,
+}
+
+/*
+    느린 유저
+
+    메시지를 뿌리는 쪽은 절대 한 사람을 기다리지 않아요. 한 사람의 큐가 꽉 찼다고
+    다른 모든 사람이 메시지를 못 받으면 안되니까요. 대신 꽉 찬 사람은 아래 중 하나로 처리합니다.
+*/
+/// 설정 파일에는 `"drop-oldest"`, `"disconnect"`, `"replay-from-db"`로 적어요.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SlowConsumerPolicy {
+    /// 가장 오래된 메시지를 버리고 새 메시지를 넣어요. 느린 유저는 중간 메시지를 못 볼 수 있어요.
+    DropOldest,
+    /// 1008(Policy Violation)로 연결을 끊어요. 클라이언트가 다시 연결해서 기록을 받으면 돼요.
+    Disconnect,
+    /// 더 쌓지 않고, 큐를 다 보낸 뒤에 놓친 메시지를 DB에서 다시 읽어서 보내줘요. 빠지는 메시지가 없어요.
+    ReplayFromDb,
+}
+
+/// PEM 파일 경로들. 인증서 파일에는 중간 인증서까지 이어 붙여도 돼요.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub certificate_path: PathBuf,
+    pub private_key_path: PathBuf,
+}
+
+/// 처음 받는 HTTP 요청(핸드셰이크 포함)에 거는 제한.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpRequestLimits {
+    /// `GET /path HTTP/1.1` 한 줄의 최대 길이.
+    pub max_request_line_length: usize,
+    /// 헤더 전체를 합친 최대 크기.
+    pub max_header_size: usize,
+    pub max_header_count: usize,
+    /// 연결되고 나서 이 시간 안에 헤더를 다 보내야 해요.
+    #[serde(with = "seconds")]
+    pub header_read_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 8080)),
+            database_url: "sqlite:db.sqlite?mode=rwc".to_string(),
+            channel_capacity: 1024,
+            slow_consumer_policy: SlowConsumerPolicy::Disconnect,
+            history_size: 10,
+            ping_interval: Duration::from_secs(30),
+            pong_timeout: Duration::from_secs(10),
+            close_timeout: Duration::from_secs(5),
+            shutdown_timeout: Duration::from_secs(10),
+            max_outbound_frame_size: 64 * 1024,
+            max_inbound_frame_size: 1024 * 1024,
+            max_inbound_message_size: 4 * 1024 * 1024,
+            message_read_timeout: Duration::from_secs(30),
+            permessage_deflate: true,
+            http_request_limits: HttpRequestLimits::default(),
+            allowed_origins: vec![],
+            tls: None,
+        }
+    }
+}
+
+impl Default for HttpRequestLimits {
+    fn default() -> Self {
+        Self {
+            max_request_line_length: 8 * 1024,
+            max_header_size: 16 * 1024,
+            max_header_count: 64,
+            header_read_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Config {
+    /*
+        타입만 맞다고 다 되는 건 아니에요.
+        0이면 tokio가 패닉하는 값들(채널 크기, interval)이나, 0이면 아무것도 못 받는 제한들을 미리 거릅니다.
+    */
+    /// 서버를 켜기 전에 불러주세요. 틀린 값이 있으면 어떤 값인지 알려줘요.
+    pub fn validate(&self) -> Result<()> {
+        let positive_sizes = [
+            ("channel_capacity", self.channel_capacity),
+            ("max_outbound_frame_size", self.max_outbound_frame_size),
+            ("max_inbound_frame_size", self.max_inbound_frame_size),
+            ("max_inbound_message_size", self.max_inbound_message_size),
+            (
+                "http_request_limits.max_request_line_length",
+                self.http_request_limits.max_request_line_length,
+            ),
+            (
+                "http_request_limits.max_header_size",
+                self.http_request_limits.max_header_size,
+            ),
+        ];
+        for (name, value) in positive_sizes {
+            if value == 0 {
+                bail!("{name} must be greater than 0");
+            }
+        }
+
+        let positive_durations = [
+            ("ping_interval", self.ping_interval),
+            ("pong_timeout", self.pong_timeout),
+            ("message_read_timeout", self.message_read_timeout),
+            (
+                "http_request_limits.header_read_timeout",
+                self.http_request_limits.header_read_timeout,
+            ),
+        ];
+        for (name, value) in positive_durations {
+            if value.is_zero() {
+                bail!("{name} must be greater than 0");
+            }
+        }
+
+        // SQLite의 LIMIT은 i64예요.
+        if i64::try_from(self.history_size).is_err() {
+            bail!("history_size is too large");
+        }
+        if !self.database_url.starts_with("sqlite:") {
+            bail!("database_url must start with sqlite:");
+        }
+
+        Ok(())
+    }
+
+    /// `--print-config`가 보여주는 모양. 그대로 설정 파일로 쓸 수 있어요.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("Config is always representable in TOML")
+    }
+}
+
+/// Duration을 초 단위 숫자로 읽고 씁니다. 소수도 돼요.
+mod seconds {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub(super) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        let seconds = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(seconds)
+            .map_err(|_| D::Error::custom(format!("invalid number of seconds: {seconds}")))
+    }
+}
@@ -1,47 +1,337 @@
+use crate::outbox::Delivery;
 use anyhow::Result;
 use sqlx::SqlitePool;
+use websocket_codec::Message;
 
-pub(crate) struct Db {
+pub struct Db {
     pool: SqlitePool,
 }
 
 impl Db {
-    pub(crate) async fn add_message(&self, message: &str) -> Result<()> {
-        sqlx::query("INSERT INTO messages (message) VALUES (?)")
-            .bind(message)
-            .execute(&self.pool)
-            .await?;
+    /// 저장된 메시지의 id를 돌려줘요. 나중에 저장된 메시지일수록 커요. (방이 달라도요)
+    /// 손님이 보낸 메시지는 `author`가 None이에요. `timestamp`는 유닉스 밀리초예요.
+    pub(crate) async fn add_message(
+        &self,
+        room: &str,
+        author: Option<&str>,
+        timestamp: i64,
+        message: &Message,
+    ) -> Result<i64> {
+        // 둘 중 하나만 채워요. (CHECK 제약)
+        let (text, bytes) = match message {
+            Message::Text(text) => (Some(text.as_str()), None),
+            Message::Binary(bytes) => (None, Some(bytes.as_slice())),
+        };
+
+        let result = sqlx::query(
+            "INSERT INTO messages (room, author, created_at, message, binary_message)
+            VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(room)
+        .bind(author)
+        .bind(timestamp)
+        .bind(text)
+        .bind(bytes)
+        .execute(&self.pool)
+        .await?;
 
-        Ok(())
+        Ok(result.last_insert_rowid())
     }
 
-    pub(crate) async fn list_messages(&self, limit: i64) -> Result<Vec<String>> {
-        let messages = sqlx::query_as::<_, (String,)>(&format!(
-            "SELECT message FROM messages
+    /// 진행 중인 쿼리가 끝나길 기다렸다가 연결들을 닫아요. 그 뒤로는 쿼리하면 에러예요.
+    pub(crate) async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    /// 가장 최근에 저장된 메시지의 id. 방과 상관없어요. 아무것도 없으면 0이에요.
+    pub(crate) async fn last_message_id(&self) -> Result<i64> {
+        let (id,) = sqlx::query_as::<_, (Option<i64>,)>("SELECT MAX(id) FROM messages")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(id.unwrap_or(0))
+    }
+
+    /// `room`의 최근 메시지를 `limit`개까지, 최근 것부터 돌려줘요.
+    pub(crate) async fn list_messages(&self, room: &str, limit: i64) -> Result<Vec<Delivery>> {
+        let rows = sqlx::query_as::<_, DeliveryRow>(
+            "SELECT id, author, created_at, message, binary_message FROM messages
+            WHERE room = ?
             ORDER BY id DESC
-            LIMIT {limit}
-        "
-        ))
+            LIMIT ?",
+        )
+        .bind(room)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries_from_rows(rows))
+    }
+
+    /// `room`에 `after_id`보다 뒤에 저장된 메시지를 오래된 것부터 `limit`개까지 돌려줘요.
+    pub(crate) async fn list_messages_after(
+        &self,
+        room: &str,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<Delivery>> {
+        let rows = sqlx::query_as::<_, DeliveryRow>(
+            "SELECT id, author, created_at, message, binary_message FROM messages
+            WHERE room = ? AND id > ?
+            ORDER BY id ASC
+            LIMIT ?",
+        )
+        .bind(room)
+        .bind(after_id)
+        .bind(limit)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(messages.into_iter().map(|(message,)| message).collect())
+        Ok(deliveries_from_rows(rows))
     }
 }
 
-pub(crate) async fn init_db() -> Result<Db> {
-    let pool = SqlitePool::connect("sqlite:db.sqlite?mode=rwc").await?;
+/// id, author, created_at, message, binary_message
+type DeliveryRow = (
+    i64,
+    Option<String>,
+    Option<i64>,
+    Option<String>,
+    Option<Vec<u8>>,
+);
 
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS messages (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            message TEXT NOT NULL
-        )
-        "#,
-    )
-    .execute(&pool)
-    .await?;
+fn deliveries_from_rows(rows: Vec<DeliveryRow>) -> Vec<Delivery> {
+    rows.into_iter()
+        .filter_map(|(id, author, timestamp, text, bytes)| {
+            Some(Delivery {
+                id,
+                author,
+                timestamp,
+                message: message_from_row(text, bytes)?,
+            })
+        })
+        .collect()
+}
+
+fn message_from_row(text: Option<String>, bytes: Option<Vec<u8>>) -> Option<Message> {
+    match (text, bytes) {
+        (Some(text), _) => Some(Message::Text(text)),
+        (None, Some(bytes)) => Some(Message::Binary(bytes)),
+        (None, None) => None,
+    }
+}
+
+/// `url`은 `sqlite:db.sqlite?mode=rwc` 같은 sqlx 주소예요.
+pub async fn init_db(url: &str) -> Result<Db> {
+    let pool = SqlitePool::connect(url).await?;
+
+    migrate(&pool).await?;
 
     Ok(Db { pool })
 }
+
+/*
+    스키마가 바뀔 때마다 여기에 하나씩 추가합니다.
+    이미 있는 항목은 절대 고치지 마세요! 이미 그 버전까지 올라간 DB는 다시 실행하지 않으니까요.
+    몇번째까지 실행했는지는 SQLite의 `PRAGMA user_version`에 적어둡니다.
+*/
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE IF NOT EXISTS messages (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        message TEXT NOT NULL
+    )
+    "#,
+    // Binary 메시지는 TEXT에 못넣으니까 따로 BLOB 컬럼을 둡니다.
+    // 둘 중 하나만 채워지니까 message의 NOT NULL도 풀어야 해요.
+    r#"
+    ALTER TABLE messages RENAME TO messages_old;
+    CREATE TABLE messages (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        message TEXT,
+        binary_message BLOB,
+        CHECK ((message IS NULL) != (binary_message IS NULL))
+    );
+    INSERT INTO messages (id, message) SELECT id, message FROM messages_old;
+    DROP TABLE messages_old;
+    "#,
+    // 방마다 따로 대화해요. 방이 생기기 전의 메시지는 전부 기본 방(room::DEFAULT_ROOM)으로 가요.
+    // 방 하나의 메시지를 id 순서로 읽는 일이 많으니 (room, id)로 인덱스를 걸어요.
+    r#"
+    ALTER TABLE messages ADD COLUMN room TEXT NOT NULL DEFAULT 'lobby';
+    CREATE INDEX messages_room_id ON messages (room, id);
+    "#,
+    // 닉네임을 정한 유저가 보낸 메시지에는 그 닉네임을 같이 적어요. 손님과 예전 메시지는 NULL이에요.
+    r#"
+    ALTER TABLE messages ADD COLUMN author TEXT;
+    "#,
+    // 언제 보낸 메시지인지(유닉스 밀리초). 예전 메시지는 알 수 없으니 NULL이에요.
+    r#"
+    ALTER TABLE messages ADD COLUMN created_at INTEGER;
+    "#,
+];
+
+async fn migrate(pool: &SqlitePool) -> Result<()> {
+    let (user_version,) = sqlx::query_as::<_, (i64,)>("PRAGMA user_version")
+        .fetch_one(pool)
+        .await?;
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(user_version as usize) {
+        let mut transaction = pool.begin().await?;
+
+        sqlx::query(migration).execute(&mut *transaction).await?;
+        sqlx::query(&format!("PRAGMA user_version = {}", version + 1))
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::room::DEFAULT_ROOM;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        // 메모리 DB는 커넥션마다 따로 생기니까 하나만 씁니다.
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_migrate_keeps_existing_text_messages() {
+        let pool = memory_pool().await;
+        sqlx::query(MIGRATIONS[0]).execute(&pool).await.unwrap();
+        sqlx::query("PRAGMA user_version = 1")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO messages (message) VALUES ('hello')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        migrate(&pool).await.unwrap();
+        let db = Db { pool };
+
+        assert_eq!(
+            db.list_messages(DEFAULT_ROOM, 10).await.unwrap(),
+            vec![Delivery {
+                id: 1,
+                author: None,
+                timestamp: None,
+                message: Message::Text("hello".to_string()),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_text_and_binary_messages_round_trip() {
+        let pool = memory_pool().await;
+        migrate(&pool).await.unwrap();
+        let db = Db { pool };
+
+        let text_id = db
+            .add_message(DEFAULT_ROOM, None, 1, &Message::Text("text".to_string()))
+            .await
+            .unwrap();
+        let binary_id = db
+            .add_message(
+                DEFAULT_ROOM,
+                Some("alice"),
+                2,
+                &Message::Binary(vec![0, 159, 146, 150]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.list_messages(DEFAULT_ROOM, 10).await.unwrap(),
+            vec![
+                Delivery {
+                    id: binary_id,
+                    author: Some("alice".to_string()),
+                    timestamp: Some(2),
+                    message: Message::Binary(vec![0, 159, 146, 150]),
+                },
+                Delivery {
+                    id: text_id,
+                    author: None,
+                    timestamp: Some(1),
+                    message: Message::Text("text".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_messages_after_returns_oldest_first_in_room() {
+        let pool = memory_pool().await;
+        migrate(&pool).await.unwrap();
+        let db = Db { pool };
+
+        let mut ids = vec![];
+        for (timestamp, (room, author, text)) in [
+            ("lobby", None, "a"),
+            ("other", None, "x"),
+            ("lobby", Some("alice"), "b"),
+            ("lobby", None, "c"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            ids.push(
+                db.add_message(
+                    room,
+                    author,
+                    timestamp as i64,
+                    &Message::Text(text.to_string()),
+                )
+                .await
+                .unwrap(),
+            );
+        }
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        assert_eq!(
+            db.list_messages_after("lobby", ids[0], 10).await.unwrap(),
+            vec![
+                Delivery {
+                    id: ids[2],
+                    author: Some("alice".to_string()),
+                    timestamp: Some(2),
+                    message: Message::Text("b".to_string()),
+                },
+                Delivery {
+                    id: ids[3],
+                    author: None,
+                    timestamp: Some(3),
+                    message: Message::Text("c".to_string()),
+                },
+            ]
+        );
+        assert_eq!(
+            db.list_messages_after("lobby", 0, 1).await.unwrap().len(),
+            1
+        );
+        assert_eq!(
+            db.list_messages_after("lobby", ids[3], 10).await.unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            db.list_messages("other", 10).await.unwrap(),
+            vec![Delivery {
+                id: ids[1],
+                author: None,
+                timestamp: Some(1),
+                message: Message::Text("x".to_string()),
+            }]
+        );
+    }
+}
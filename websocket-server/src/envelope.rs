@@ -0,0 +1,214 @@
+use crate::{message::unix_millis, outbox::Delivery};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use websocket_codec::Message;
+
+/*
+    chat.v2의 메시지 봉투(envelope)
+
+    예전처럼 내용만 보내면 클라이언트는 채팅인지 `* alice joined` 같은 알림인지 구분할 수 없고,
+    누가 언제 보냈는지, DB의 몇 번 메시지인지도 몰라요.
+    그래서 chat.v2를 고른 클라이언트와는 Text frame 하나에 JSON 하나씩 주고받아요.
+
+    서버 -> 클라이언트
+        {"v":2,"type":"message","id":12,"room":"lobby","author":"alice","timestamp":1700000000000,"body":"hi"}
+        - type: message(채팅), notice(알림), error(보낸 봉투가 잘못됐어요)
+        - id: DB의 메시지 id. message만 있고 나머지는 null이에요.
+        - author: 손님이 보냈거나 message가 아니면 null.
+        - timestamp: 유닉스 밀리초. 시각을 적기 전에 저장된 메시지면 null.
+        - body: Binary 메시지는 JSON에 넣을 수 없으니 `[binary 3 bytes]`처럼 크기만 알려줘요.
+
+    클라이언트 -> 서버
+        {"type":"message","body":"hi"}
+        {"type":"nick","body":"alice"}
+        {"type":"resume","since":42}        첫 메시지로만 보낼 수 있어요. (resume.rs)
+        - v: 빼도 돼요. 적었다면 2여야 해요.
+        - message와 nick에는 body가, resume에는 since가 있어야 해요.
+        - 모르는 필드가 있으면 받지 않아요. 오타를 조용히 무시하면 찾기 어려우니까요.
+        잘못된 봉투를 받으면 연결은 그대로 두고 error로 대답해요.
+
+    스키마가 바뀌면 VERSION을 올리고 subprotocol(chat.v3)도 새로 만들어요.
+*/
+
+/// 봉투 모양의 버전. subprotocol 이름(chat.v2)의 숫자와 같아요.
+pub(crate) const VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum EnvelopeType {
+    Message,
+    Notice,
+    Error,
+}
+
+#[derive(Serialize)]
+struct ServerEnvelope<'a> {
+    v: u32,
+    #[serde(rename = "type")]
+    kind: EnvelopeType,
+    id: Option<i64>,
+    room: &'a str,
+    author: Option<&'a str>,
+    timestamp: Option<i64>,
+    body: Cow<'a, str>,
+}
+
+impl ServerEnvelope<'_> {
+    fn to_json(&self) -> String {
+        // 문자열과 숫자뿐이라 실패할 일이 없어요.
+        serde_json::to_string(self).expect("envelope is always serializable")
+    }
+}
+
+/// `room`에 저장된 메시지 하나.
+pub(crate) fn encode_delivery(delivery: &Delivery, room: &str) -> String {
+    let body = match &delivery.message {
+        Message::Text(text) => Cow::Borrowed(text.as_str()),
+        Message::Binary(bytes) => Cow::Owned(format!("[binary {} bytes]", bytes.len())),
+    };
+    ServerEnvelope {
+        v: VERSION,
+        kind: EnvelopeType::Message,
+        id: Some(delivery.id),
+        room,
+        author: delivery.author.as_deref(),
+        timestamp: delivery.timestamp,
+        body,
+    }
+    .to_json()
+}
+
+/// DB에 없는 notice나 error. 시각은 지금이에요.
+pub(crate) fn encode_text(kind: EnvelopeType, room: &str, body: &str) -> String {
+    ServerEnvelope {
+        v: VERSION,
+        kind,
+        id: None,
+        room,
+        author: None,
+        timestamp: Some(unix_millis()),
+        body: Cow::Borrowed(body),
+    }
+    .to_json()
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientEnvelope {
+    #[serde(default)]
+    v: Option<u32>,
+    #[serde(rename = "type")]
+    kind: ClientEnvelopeType,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    since: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ClientEnvelopeType {
+    Message,
+    Nick,
+    Resume,
+}
+
+/// 클라이언트가 봉투로 부탁한 것.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Request {
+    /// 같은 방 사람들에게 보낼 채팅.
+    Message(String),
+    /// 닉네임을 정하거나 바꿔주세요. 쓸 수 있는 이름인지는 따로 봐요.
+    Nick(String),
+    /// 이 id까지는 받았으니 그 뒤부터 보내주세요.
+    Resume(i64),
+}
+
+/// 잘못된 봉투면 클라이언트에게 error로 돌려줄 이유를 돌려줘요.
+pub(crate) fn decode(text: &str) -> Result<Request, String> {
+    let envelope: ClientEnvelope =
+        serde_json::from_str(text).map_err(|error| format!("Invalid envelope: {error}"))?;
+
+    if let Some(version) = envelope.v.filter(|version| *version != VERSION) {
+        return Err(format!(
+            "Unsupported envelope version {version}, expected {VERSION}"
+        ));
+    }
+
+    match (envelope.kind, envelope.body, envelope.since) {
+        (ClientEnvelopeType::Message, Some(body), None) if body.is_empty() => {
+            Err("Message body must not be empty".to_string())
+        }
+        (ClientEnvelopeType::Message, Some(body), None) => Ok(Request::Message(body)),
+        (ClientEnvelopeType::Nick, Some(body), None) => Ok(Request::Nick(body)),
+        (ClientEnvelopeType::Resume, None, Some(since)) if since >= 0 => Ok(Request::Resume(since)),
+        (ClientEnvelopeType::Resume, None, Some(_)) => {
+            Err("Resume since must not be negative".to_string())
+        }
+        (ClientEnvelopeType::Message | ClientEnvelopeType::Nick, _, _) => {
+            Err("Message and nick need only a body".to_string())
+        }
+        (ClientEnvelopeType::Resume, _, _) => Err("Resume needs only since".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_delivery() {
+        let delivery = Delivery {
+            id: 12,
+            author: Some("alice".to_string()),
+            timestamp: Some(1_700_000_000_000),
+            message: Message::Text("say \"hi\"".to_string()),
+        };
+        assert_eq!(
+            encode_delivery(&delivery, "lobby"),
+            r#"{"v":2,"type":"message","id":12,"room":"lobby","author":"alice","timestamp":1700000000000,"body":"say \"hi\""}"#
+        );
+
+        let delivery = Delivery {
+            id: 13,
+            author: None,
+            timestamp: None,
+            message: Message::Binary(vec![1, 2, 3]),
+        };
+        assert_eq!(
+            encode_delivery(&delivery, "rust"),
+            r#"{"v":2,"type":"message","id":13,"room":"rust","author":null,"timestamp":null,"body":"[binary 3 bytes]"}"#
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode(r#"{"type":"message","body":"hi"}"#),
+            Ok(Request::Message("hi".to_string()))
+        );
+        assert_eq!(
+            decode(r#"{"v":2,"type":"nick","body":"alice"}"#),
+            Ok(Request::Nick("alice".to_string()))
+        );
+        assert_eq!(
+            decode(r#"{"type":"resume","since":42}"#),
+            Ok(Request::Resume(42))
+        );
+
+        for text in [
+            "hi",
+            r#"{"body":"hi"}"#,
+            r#"{"type":"notice","body":"hi"}"#,
+            r#"{"type":"message","body":"hi","id":3}"#,
+            r#"{"type":"message","body":1}"#,
+            r#"{"type":"message","body":""}"#,
+            r#"{"v":1,"type":"message","body":"hi"}"#,
+            r#"{"type":"message","body":"hi","since":3}"#,
+            r#"{"type":"resume","body":"42"}"#,
+            r#"{"type":"resume","since":-1}"#,
+        ] {
+            assert!(decode(text).is_err(), "{text}");
+        }
+    }
+}
@@ -1,111 +1,540 @@
+use crate::{config::HttpRequestLimits, subprotocol::Subprotocol};
 use anyhow::Result;
-use base64::Engine;
-use sha1::Digest;
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::TcpStream,
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use websocket_codec::{
+    deflate::DeflateConfig,
+    handshake::{generate_accept_key, is_valid_key, SUPPORTED_VERSION},
 };
 
+/// 핸드셰이크에서 클라이언트와 합의한 것들.
+pub(crate) struct Negotiated {
+    pub(crate) deflate: Option<DeflateConfig>,
+    pub(crate) subprotocol: Option<Subprotocol>,
+}
+
+/// 웹소켓 핸드셰이크 요청이 잘못됐을 때. 각각 HTTP 에러 응답으로 돌려줍니다.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum HandshakeError {
+    /// 400 Bad Request
+    BadRequest(&'static str),
+    /// 426 Upgrade Required. 우리가 아는 버전(13)을 알려줘야 해요.
+    UnsupportedVersion,
+    /// 403 Forbidden. 허락하지 않은 사이트의 페이지에서 연결하려고 해요.
+    ForbiddenOrigin,
+}
+
+impl HandshakeError {
+    pub(crate) fn to_response(&self) -> String {
+        match self {
+            HandshakeError::BadRequest(reason) => http_response("400 Bad Request", &[], reason),
+            HandshakeError::UnsupportedVersion => http_response(
+                "426 Upgrade Required",
+                &[("Sec-WebSocket-Version", SUPPORTED_VERSION)],
+                "Unsupported Sec-WebSocket-Version",
+            ),
+            HandshakeError::ForbiddenOrigin => {
+                http_response("403 Forbidden", &[], "Origin not allowed")
+            }
+        }
+    }
+}
+
+/// 에러 응답처럼 본문이 짧은 HTTP 응답을 만듭니다. 응답을 보내고 나면 연결은 닫아요.
+pub(crate) fn http_response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+    http_response_with_content_type(status, "text/plain; charset=utf-8", headers, body)
+}
+
+pub(crate) fn http_response_with_content_type(
+    status: &str,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> String {
+    let mut response = format!("HTTP/1.1 {status}\r\n");
+    for (key, value) in headers {
+        response.push_str(&format!("{key}: {value}\r\n"));
+    }
+    response.push_str(&format!("Content-Type: {content_type}\r\n"));
+    response.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    response.push_str("Connection: close\r\n");
+    response.push_str("\r\n");
+    response.push_str(body);
+    response
+}
+
+/*
+    RFC 6455 4.2.1. 서버가 받아야 하는 핸드셰이크 요청
+    1. GET 요청이고 HTTP/1.1
+    2. Host 헤더가 있음
+    3. Upgrade 헤더에 "websocket"이 있음 (대소문자 구분 X)
+    4. Connection 헤더에 "Upgrade"가 있음 (대소문자 구분 X, "keep-alive, Upgrade" 같은 목록일 수 있음)
+    5. Sec-WebSocket-Key를 base64로 풀면 16바이트
+    6. Sec-WebSocket-Version이 13
+*/
+pub(crate) fn validate_websocket_upgrade_request(
+    request: &HttpRequest,
+) -> Result<(), HandshakeError> {
+    if request.method != "GET" {
+        return Err(HandshakeError::BadRequest("Method must be GET"));
+    }
+    if request.protocol != "HTTP/1.1" {
+        return Err(HandshakeError::BadRequest("HTTP/1.1 is required"));
+    }
+    if request.header_values("Host").next().is_none() {
+        return Err(HandshakeError::BadRequest("Host header is required"));
+    }
+    if !request.has_header_token("Upgrade", "websocket") {
+        return Err(HandshakeError::BadRequest(
+            "Upgrade header must contain websocket",
+        ));
+    }
+    if !request.has_header_token("Connection", "Upgrade") {
+        return Err(HandshakeError::BadRequest(
+            "Connection header must contain Upgrade",
+        ));
+    }
+
+    let mut keys = request.header_values("Sec-WebSocket-Key");
+    let (Some(key), None) = (keys.next(), keys.next()) else {
+        return Err(HandshakeError::BadRequest(
+            "Exactly one Sec-WebSocket-Key header is required",
+        ));
+    };
+    if !is_valid_key(key) {
+        return Err(HandshakeError::BadRequest(
+            "Sec-WebSocket-Key must be 16 bytes encoded in base64",
+        ));
+    }
+
+    let mut versions = request.header_values("Sec-WebSocket-Version");
+    if !matches!(
+        (versions.next(), versions.next()),
+        (Some(SUPPORTED_VERSION), None)
+    ) {
+        return Err(HandshakeError::UnsupportedVersion);
+    }
+
+    Ok(())
+}
+
+/// `validate_websocket_upgrade_request`를 통과한 요청에만 불러주세요.
 pub(crate) async fn send_websocket_upgrade_response(
-    tcp_stream: &mut TcpStream,
+    tcp_stream: &mut (impl AsyncWrite + Unpin),
     request: &HttpRequest,
-) -> Result<()> {
+    enable_permessage_deflate: bool,
+) -> Result<Negotiated> {
     let key = request
-        .headers
-        .iter()
-        .find_map(|(key, value)| {
-            if key == "Sec-WebSocket-Key" {
-                Some(value)
-            } else {
-                None
-            }
-        })
+        .header_values("Sec-WebSocket-Key")
+        .next()
         .ok_or(anyhow::anyhow!("Sec-WebSocket-Key not found"))?;
 
     let aceept_key = generate_accept_key(key);
 
+    let deflate = if enable_permessage_deflate {
+        DeflateConfig::negotiate(request.header_values("Sec-WebSocket-Extensions"))
+    } else {
+        None
+    };
+    let subprotocol = Subprotocol::negotiate(request.header_values("Sec-WebSocket-Protocol"));
+
     let mut response = String::new();
 
     response.push_str("HTTP/1.1 101 Switching Protocols\r\n");
     response.push_str(&format!("Sec-WebSocket-Accept: {aceept_key}\r\n"));
     response.push_str("Connection: Upgrade\r\n");
     response.push_str("Upgrade: websocket\r\n");
+    if let Some(subprotocol) = subprotocol {
+        response.push_str(&format!(
+            "Sec-WebSocket-Protocol: {}\r\n",
+            subprotocol.name()
+        ));
+    }
+    if let Some(deflate) = &deflate {
+        response.push_str(&format!(
+            "Sec-WebSocket-Extensions: {}\r\n",
+            deflate.response_header_value()
+        ));
+    }
     response.push_str("\r\n");
 
     tcp_stream.write_all(response.as_bytes()).await?;
+    tcp_stream.flush().await?;
 
-    Ok(())
-}
-
-fn generate_accept_key(client_key: &str) -> String {
-    let guid = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
-
-    let mut hasher = sha1::Sha1::new();
-    hasher.update(client_key);
-    hasher.update(guid);
-    let result = hasher.finalize();
-
-    base64::engine::general_purpose::STANDARD.encode(result)
+    Ok(Negotiated {
+        deflate,
+        subprotocol,
+    })
 }
 
 #[derive(Debug)]
 pub(crate) struct HttpRequest {
     pub(crate) method: String,
     pub(crate) path: String,
-    _protocol: String,
+    protocol: String,
     headers: Vec<(String, String)>,
 }
 impl HttpRequest {
-    pub(crate) fn is_websocket_upgrade_request(&self) -> bool {
+    #[cfg(test)]
+    pub(crate) fn new(method: &str, path: &str, headers: &[(&str, &str)]) -> Self {
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            protocol: "HTTP/1.1".to_string(),
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    /// 이름이 같은 헤더들의 값. 헤더 이름은 대소문자를 구분하지 않아요.
+    pub(crate) fn header_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
         self.headers
             .iter()
-            .any(|(key, value)| key == "Upgrade" && value == "websocket")
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// `Connection: keep-alive, Upgrade`처럼 쉼표로 나열된 헤더에 그 값이 있는지.
+    /// 값도 대소문자를 구분하지 않아요.
+    fn has_header_token(&self, name: &str, token: &str) -> bool {
+        self.header_values(name)
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// 웹소켓으로 바꾸고 싶다는 요청인지. 제대로 된 요청인지는 따로 검사합니다.
+    pub(crate) fn is_websocket_upgrade_request(&self) -> bool {
+        self.has_header_token("Upgrade", "websocket")
     }
 }
 
-pub(crate) async fn receive_http_request(tcp_stream: &mut TcpStream) -> Result<HttpRequest> {
-    let mut buf_reader = BufReader::new(tcp_stream);
+/// `/rooms/rust?since=3&nick=alice`에서 `key`의 값. 퍼센트 디코딩은 하지 않아요.
+pub(crate) fn query_value<'a>(path: &'a str, key: &str) -> Option<&'a str> {
+    let (_, query) = path.split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        (name == key).then_some(value)
+    })
+}
 
-    let mut line = String::new();
-    buf_reader.read_line(&mut line).await?;
+#[derive(Debug)]
+pub(crate) enum HttpRequestError {
+    Io(std::io::Error),
+    /// 요청을 다 받기 전에 연결이 끊겼어요.
+    UnexpectedEof,
+    /// 제한 시간 안에 헤더를 다 보내지 않았어요. (slowloris)
+    Timeout,
+    MalformedRequestLine,
+    MalformedHeader,
+    RequestLineTooLong,
+    HeadersTooLarge,
+    TooManyHeaders,
+}
 
-    let mut iter = line.split_whitespace();
+impl std::fmt::Display for HttpRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpRequestError::Io(error) => write!(f, "IO error: {error}"),
+            HttpRequestError::UnexpectedEof => write!(f, "Unexpected EOF"),
+            HttpRequestError::Timeout => write!(f, "Timeout"),
+            HttpRequestError::MalformedRequestLine => write!(f, "Malformed request line"),
+            HttpRequestError::MalformedHeader => write!(f, "Malformed header"),
+            HttpRequestError::RequestLineTooLong => write!(f, "Request line too long"),
+            HttpRequestError::HeadersTooLarge => write!(f, "Headers too large"),
+            HttpRequestError::TooManyHeaders => write!(f, "Too many headers"),
+        }
+    }
+}
+
+impl HttpRequestError {
+    /// 클라이언트에게 돌려줄 에러 응답. 연결이 이미 끊겼으면 None.
+    pub(crate) fn to_response(&self) -> Option<String> {
+        let response = match self {
+            HttpRequestError::Io(_) | HttpRequestError::UnexpectedEof => return None,
+            HttpRequestError::Timeout => {
+                http_response("408 Request Timeout", &[], "Request timeout")
+            }
+            HttpRequestError::MalformedRequestLine => {
+                http_response("400 Bad Request", &[], "Malformed request line")
+            }
+            HttpRequestError::MalformedHeader => {
+                http_response("400 Bad Request", &[], "Malformed header")
+            }
+            HttpRequestError::RequestLineTooLong => {
+                http_response("400 Bad Request", &[], "Request line too long")
+            }
+            HttpRequestError::HeadersTooLarge | HttpRequestError::TooManyHeaders => http_response(
+                "431 Request Header Fields Too Large",
+                &[],
+                "Request header fields too large",
+            ),
+        };
+        Some(response)
+    }
+}
+
+/*
+    HTTP 요청은 믿을 수 없는 사람이 보내는 거라서 조심해야 해요.
+    - 이상한 요청에 unwrap하다가 패닉나면 안되고
+    - 끝없이 긴 줄이나 끝없이 많은 헤더로 메모리를 다 먹으면 안되고
+    - 한 글자씩 아주 천천히 보내면서 연결을 붙잡고 있어도 안됩니다. (slowloris)
+*/
+pub(crate) async fn receive_http_request(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    limits: &HttpRequestLimits,
+) -> Result<HttpRequest, HttpRequestError> {
+    tokio::time::timeout(
+        limits.header_read_timeout,
+        parse_http_request(reader, limits),
+    )
+    .await
+    .map_err(|_| HttpRequestError::Timeout)?
+}
 
-    let method = iter.next().unwrap();
-    let path = iter.next().unwrap();
-    let protocol = iter.next().unwrap();
+async fn parse_http_request(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    limits: &HttpRequestLimits,
+) -> Result<HttpRequest, HttpRequestError> {
+    let line = read_line(reader, limits.max_request_line_length)
+        .await?
+        .ok_or(HttpRequestError::RequestLineTooLong)?;
+
+    let mut iter = line.split(' ');
+    let (Some(method), Some(path), Some(protocol), None) =
+        (iter.next(), iter.next(), iter.next(), iter.next())
+    else {
+        return Err(HttpRequestError::MalformedRequestLine);
+    };
+    if method.is_empty() || path.is_empty() || !protocol.starts_with("HTTP/") {
+        return Err(HttpRequestError::MalformedRequestLine);
+    }
 
     let mut headers = Vec::new();
+    let mut remaining_header_size = limits.max_header_size;
 
     loop {
-        let mut line = String::new();
-        buf_reader.read_line(&mut line).await?;
+        let line = read_line(reader, remaining_header_size)
+            .await?
+            .ok_or(HttpRequestError::HeadersTooLarge)?;
+        remaining_header_size -= line.len();
 
-        if line == "\r\n" {
+        if line.is_empty() {
             break;
         }
 
-        let (key, value) = line.split_once(':').unwrap();
+        if headers.len() == limits.max_header_count {
+            return Err(HttpRequestError::TooManyHeaders);
+        }
+
+        let (key, value) = line
+            .split_once(':')
+            .ok_or(HttpRequestError::MalformedHeader)?;
 
-        headers.push((key.trim().to_string(), value.trim().to_string()));
+        // "Host : x" 처럼 이름 뒤에 공백이 있거나, 공백으로 시작하는 줄(obs-fold)은 받지 않아요.
+        if key.is_empty() || key.contains(|c: char| c.is_ascii_whitespace()) {
+            return Err(HttpRequestError::MalformedHeader);
+        }
+
+        headers.push((key.to_string(), value.trim().to_string()));
     }
 
     Ok(HttpRequest {
         method: method.to_string(),
         path: path.to_string(),
-        _protocol: protocol.to_string(),
+        protocol: protocol.to_string(),
         headers,
     })
 }
 
+/// 한 줄을 읽어서 줄바꿈(`\r\n` 또는 `\n`)을 뗀 채로 돌려줍니다.
+/// 줄바꿈 없이 `max_length`바이트를 넘어가면 None.
+async fn read_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    max_length: usize,
+) -> Result<Option<String>, HttpRequestError> {
+    let mut line = Vec::new();
+
+    // 줄바꿈까지 포함해서 max_length + 2 바이트까지만 읽어요.
+    let limit = max_length as u64 + 2;
+    reader
+        .take(limit)
+        .read_until(b'\n', &mut line)
+        .await
+        .map_err(HttpRequestError::Io)?;
+
+    if !line.ends_with(b"\n") {
+        if line.len() as u64 == limit {
+            return Ok(None);
+        }
+        return Err(HttpRequestError::UnexpectedEof);
+    }
+
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    if line.len() > max_length {
+        return Ok(None);
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| HttpRequestError::MalformedHeader)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn upgrade_request(headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest::new("GET", "/", headers)
+    }
+
+    const VALID_HEADERS: &[(&str, &str)] = &[
+        ("host", "localhost:8080"),
+        ("UPGRADE", "WebSocket"),
+        ("connection", "keep-alive, Upgrade"),
+        ("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="),
+        ("Sec-WebSocket-Version", "13"),
+    ];
+
     #[test]
-    fn test_generate_accept_key() {
-        let client_key = "dGhlIHNhbXBsZSBub25jZQ==";
-        let accept_key = generate_accept_key(client_key);
-        assert_eq!(accept_key, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    fn test_validate_websocket_upgrade_request() {
+        let request = upgrade_request(VALID_HEADERS);
+        assert!(request.is_websocket_upgrade_request());
+        assert_eq!(validate_websocket_upgrade_request(&request), Ok(()));
+
+        let mut request = upgrade_request(VALID_HEADERS);
+        request.method = "POST".to_string();
+        assert!(matches!(
+            validate_websocket_upgrade_request(&request),
+            Err(HandshakeError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_websocket_upgrade_request_rejects_bad_headers() {
+        let replace = |name: &str, value: &str| {
+            let mut headers = VALID_HEADERS
+                .iter()
+                .filter(|(key, _)| !key.eq_ignore_ascii_case(name))
+                .copied()
+                .collect::<Vec<_>>();
+            headers.push((name, value));
+            validate_websocket_upgrade_request(&upgrade_request(&headers))
+        };
+
+        assert!(matches!(
+            replace("Connection", "keep-alive"),
+            Err(HandshakeError::BadRequest(_))
+        ));
+        // 12바이트짜리 키
+        assert!(matches!(
+            replace("Sec-WebSocket-Key", "AAAAAAAAAAAAAAAA"),
+            Err(HandshakeError::BadRequest(_))
+        ));
+        assert!(matches!(
+            replace("Sec-WebSocket-Key", "not base64!"),
+            Err(HandshakeError::BadRequest(_))
+        ));
+        assert_eq!(
+            replace("Sec-WebSocket-Version", "8"),
+            Err(HandshakeError::UnsupportedVersion)
+        );
+    }
+
+    #[test]
+    fn test_unsupported_version_response_lists_supported_versions() {
+        let response = HandshakeError::UnsupportedVersion.to_response();
+        assert!(response.starts_with("HTTP/1.1 426 Upgrade Required\r\n"));
+        assert!(response.contains("\r\nSec-WebSocket-Version: 13\r\n"));
+    }
+
+    const LIMITS: HttpRequestLimits = HttpRequestLimits {
+        max_request_line_length: 64,
+        max_header_size: 128,
+        max_header_count: 4,
+        header_read_timeout: std::time::Duration::from_secs(1),
+    };
+
+    async fn parse(mut request: &[u8]) -> Result<HttpRequest, HttpRequestError> {
+        receive_http_request(&mut request, &LIMITS).await
+    }
+
+    #[tokio::test]
+    async fn test_receive_http_request() {
+        let request = parse(b"GET /rooms HTTP/1.1\r\nHost: localhost\nX-Empty:\r\n\r\nbody")
+            .await
+            .unwrap();
+
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/rooms");
+        assert_eq!(
+            request.headers,
+            vec![
+                ("Host".to_string(), "localhost".to_string()),
+                ("X-Empty".to_string(), "".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receive_http_request_rejects_malformed_requests() {
+        assert!(matches!(
+            parse(b"GET /\r\n\r\n").await,
+            Err(HttpRequestError::MalformedRequestLine)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nno colon\r\n\r\n").await,
+            Err(HttpRequestError::MalformedHeader)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nHost : x\r\n\r\n").await,
+            Err(HttpRequestError::MalformedHeader)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\nHost: x\r\n").await,
+            Err(HttpRequestError::UnexpectedEof)
+        ));
+        assert!(matches!(
+            parse(b"").await,
+            Err(HttpRequestError::UnexpectedEof)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_receive_http_request_enforces_limits() {
+        let long_path = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(64));
+        assert!(matches!(
+            parse(long_path.as_bytes()).await,
+            Err(HttpRequestError::RequestLineTooLong)
+        ));
+
+        let large_header = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(128));
+        assert!(matches!(
+            parse(large_header.as_bytes()).await,
+            Err(HttpRequestError::HeadersTooLarge)
+        ));
+
+        let many_headers = format!("GET / HTTP/1.1\r\n{}\r\n", "A: b\r\n".repeat(5));
+        assert!(matches!(
+            parse(many_headers.as_bytes()).await,
+            Err(HttpRequestError::TooManyHeaders)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_receive_http_request_times_out_on_slow_clients() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut reader = tokio::io::BufReader::new(server);
+
+        let mut client = client;
+        client.write_all(b"GET / HTTP/1.1\r\nHo").await.unwrap();
+
+        assert!(matches!(
+            receive_http_request(&mut reader, &LIMITS).await,
+            Err(HttpRequestError::Timeout)
+        ));
     }
 }
@@ -0,0 +1,242 @@
+use crate::{
+    metrics,
+    outbox::{Delivery, Outbox, Pushed},
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use websocket_codec::CloseFrame;
+
+/*
+    지금 연결된 유저들의 목록.
+
+    예전에는 `Mutex<Vec<UserTx>>` 하나였어요.
+    - 메시지를 뿌릴 때마다 전체 lock을 잡고 Vec을 처음부터 끝까지 훑고
+    - 누가 나갈 때도 같은 lock을 잡고 retain으로 Vec을 다 훑었어요.
+    유저가 수만 명이면 나가는 사람 한 명이 O(n)이고, 그동안 들어오는 사람, 나가는 사람, 뿌리는 사람 모두 기다려요.
+
+    그래서 목록을 여러 조각(shard)으로 나눴어요. 유저는 id로 정해진 조각 하나에만 들어가요.
+    - 들어오기/나가기: 그 조각 하나만 잠그고 넣고 빼요. O(1)
+    - 뿌리기: 조각을 하나씩 잠깐 잠그면서 push해요. push는 절대 기다리지 않으니(Outbox 참고) lock도 금방 풀려요.
+    다른 조각을 쓰는 사람들은 서로 기다리지 않아요.
+
+    lock 안에서 await하는 일이 없으니 tokio Mutex 대신 std Mutex를 써요.
+*/
+pub(crate) struct Hub {
+    shards: Box<[Mutex<Shard>]>,
+}
+
+/*
+    뿌릴 때는 모두를 차례로 훑으니 Vec이 빠르고, 나갈 때는 한 명을 찾아야 하니 HashMap이 빨라요.
+    그래서 둘 다 둬요. 나갈 때는 마지막 유저를 그 자리로 옮기고(swap_remove) 위치만 고쳐주면 O(1)이에요.
+*/
+#[derive(Default)]
+struct Shard {
+    users: Vec<(u64, Arc<Outbox>)>,
+    /// id -> users 안의 위치
+    positions: HashMap<u64, usize>,
+}
+
+impl Shard {
+    fn insert(&mut self, id: u64, outbox: Arc<Outbox>) {
+        match self.positions.get(&id) {
+            Some(&position) => self.users[position].1 = outbox,
+            None => {
+                self.positions.insert(id, self.users.len());
+                self.users.push((id, outbox));
+            }
+        }
+    }
+
+    fn remove(&mut self, id: u64) {
+        let Some(position) = self.positions.remove(&id) else {
+            return;
+        };
+        self.users.swap_remove(position);
+        if let Some((moved_id, _)) = self.users.get(position) {
+            self.positions.insert(*moved_id, position);
+        }
+    }
+}
+
+impl Hub {
+    /// 사람이 많이 모이는 곳에 써요. 조각 수는 코어 수에 맞춰요.
+    pub(crate) fn new() -> Self {
+        // 코어보다 넉넉히 나눠야 동시에 같은 조각을 잡는 일이 드물어요.
+        let shard_count = std::thread::available_parallelism()
+            .map_or(1, |parallelism| parallelism.get())
+            .saturating_mul(4)
+            .next_power_of_two();
+
+        Self::with_shard_count(shard_count)
+    }
+
+    /// 뿌릴 때마다 모든 조각을 잠가요. 몇 명 없는 곳이면 조각이 적은 게 나아요.
+    pub(crate) fn with_shard_count(shard_count: usize) -> Self {
+        assert!(shard_count > 0, "a hub needs at least one shard");
+        Self {
+            shards: (0..shard_count)
+                .map(|_| Mutex::new(Shard::default()))
+                .collect(),
+        }
+    }
+
+    fn shard(&self, id: u64) -> &Mutex<Shard> {
+        // id는 1씩 늘어나니 나머지로 고르면 골고루 나눠져요.
+        &self.shards[(id % self.shards.len() as u64) as usize]
+    }
+
+    pub(crate) fn join(&self, id: u64, outbox: Arc<Outbox>) {
+        self.shard(id).lock().unwrap().insert(id, outbox);
+    }
+
+    /// 이미 나갔으면 아무것도 안 해요.
+    pub(crate) fn leave(&self, id: u64) {
+        self.shard(id).lock().unwrap().remove(id);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().users.len())
+            .sum()
+    }
+
+    /// `from`을 뺀 모두에게 보내요. 꽉 찬 유저는 SlowConsumerPolicy대로 처리하고 바로 다음 사람으로 넘어가요.
+    pub(crate) fn broadcast(&self, delivery: &Delivery, from: u64) {
+        self.push_to_all(
+            from,
+            |outbox| outbox.push(delivery.clone()),
+            |sender| sender.note_own_message(delivery.id),
+        );
+    }
+
+    /// 알림을 `from`을 뺀 모두에게 보내요.
+    pub(crate) fn broadcast_notice(&self, notice: &str, from: u64) {
+        self.push_to_all(
+            from,
+            |outbox| outbox.push_notice(notice.to_string()),
+            |_| {},
+        );
+    }
+
+    fn push_to_all(
+        &self,
+        from: u64,
+        push: impl Fn(&Outbox) -> Pushed,
+        skip_sender: impl Fn(&Outbox),
+    ) {
+        let mut disconnected = vec![];
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock().unwrap();
+            // Q. 목록에는 나를 포함해서 다 있는데, 나를 빼고 보내려면 어떻게 해야합니까?
+            // A. 유저마다 고유한 id가 있으니, 내 id만 건너뛰면 되겠네!
+            for (id, outbox) in &shard.users {
+                if *id == from {
+                    skip_sender(outbox);
+                    continue;
+                }
+                match push(outbox) {
+                    Pushed::DroppedOldest => {
+                        metrics::count(&metrics::SLOW_CONSUMER_DROPPED_MESSAGES)
+                    }
+                    Pushed::StartedLagging => metrics::count(&metrics::SLOW_CONSUMER_REPLAYS),
+                    Pushed::Disconnected => {
+                        metrics::count(&metrics::SLOW_CONSUMER_DISCONNECTS);
+                        println!("user {id}: Too slow, disconnecting");
+                        disconnected.push(*id);
+                    }
+                    Pushed::Queued | Pushed::Lagging | Pushed::SkippedNotice | Pushed::Closed => {}
+                }
+            }
+            // 끊을 사람에게는 더 뿌리지 않아요.
+            for id in disconnected.drain(..) {
+                shard.remove(id);
+            }
+        }
+    }
+
+    /// 모두를 목록에서 빼고, 쌓인 메시지를 보낸 뒤 `close_frame`으로 닫게 해요. 뺀 유저 수를 돌려줘요.
+    pub(crate) fn close_all(&self, close_frame: CloseFrame) -> usize {
+        let mut closed = 0;
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock().unwrap();
+            shard.positions.clear();
+            for (_, outbox) in shard.users.drain(..) {
+                outbox.close(close_frame.clone());
+                closed += 1;
+            }
+        }
+        closed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::SlowConsumerPolicy, outbox::Outgoing};
+    use websocket_codec::{CloseCode, Message};
+
+    fn delivery(id: i64) -> Delivery {
+        Delivery {
+            id,
+            author: None,
+            timestamp: None,
+            message: Message::Text(id.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_skips_sender_and_left_users() {
+        let hub = Hub::new();
+        let outboxes: Vec<_> = (0..3)
+            .map(|_| Arc::new(Outbox::new(4, SlowConsumerPolicy::Disconnect)))
+            .collect();
+        for (id, outbox) in outboxes.iter().enumerate() {
+            hub.join(id as u64, outbox.clone());
+        }
+        hub.leave(2);
+        hub.leave(2);
+        assert_eq!(hub.len(), 2);
+
+        hub.broadcast(&delivery(1), 0);
+
+        assert_eq!(outboxes[1].recv().await, Outgoing::Message(delivery(1)));
+        assert_eq!(hub.close_all(CloseFrame::new(CloseCode::GOING_AWAY, "")), 2);
+        // 보낸 사람은 자기 메시지 없이 바로 Close만 받아요.
+        assert!(matches!(outboxes[0].recv().await, Outgoing::Close(_)));
+        assert!(matches!(outboxes[1].recv().await, Outgoing::Close(_)));
+        assert_eq!(hub.len(), 0);
+    }
+
+    #[test]
+    fn test_disconnected_slow_consumer_leaves() {
+        let hub = Hub::with_shard_count(1);
+        hub.join(1, Arc::new(Outbox::new(1, SlowConsumerPolicy::Disconnect)));
+
+        hub.broadcast(&delivery(1), 0);
+        assert_eq!(hub.len(), 1);
+        hub.broadcast(&delivery(2), 0);
+        assert_eq!(hub.len(), 0);
+    }
+
+    #[test]
+    fn test_leave_keeps_positions_of_moved_users() {
+        let mut shard = Shard::default();
+        for id in 0..4 {
+            shard.insert(id, Arc::new(Outbox::new(1, SlowConsumerPolicy::Disconnect)));
+        }
+        shard.remove(1);
+        shard.remove(1);
+        shard.remove(3);
+
+        let ids: Vec<_> = shard.users.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![0, 2]);
+        for (position, (id, _)) in shard.users.iter().enumerate() {
+            assert_eq!(shard.positions[id], position);
+        }
+        assert_eq!(shard.positions.len(), 2);
+    }
+}
@@ -0,0 +1,1252 @@
+/// benches/hub.rs에서만 써요. `bench` feature를 켜야 있어요.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+mod cli;
+mod config;
+mod db;
+mod envelope;
+mod handshake;
+mod hub;
+mod message;
+mod metrics;
+mod nickname;
+mod origin;
+mod outbox;
+mod reader;
+mod resume;
+mod room;
+mod subprotocol;
+mod tls;
+
+use anyhow::Result;
+pub use cli::{parse_command, Command, USAGE};
+pub use config::{Config, HttpRequestLimits, SlowConsumerPolicy, TlsConfig};
+pub use db::{init_db, Db};
+use envelope::{EnvelopeType, Request};
+use handshake::{
+    http_response, http_response_with_content_type, receive_http_request,
+    send_websocket_upgrade_response, validate_websocket_upgrade_request, HandshakeError,
+    HttpRequest,
+};
+use message::unix_millis;
+use nickname::{is_valid_nickname, nickname_from_path, parse_nick_command, MAX_NICKNAME_LENGTH};
+use origin::{cors_headers, is_allowed_origin, PREFLIGHT_HEADERS};
+use outbox::{Delivery, Outbox, Outgoing};
+use reader::{FrameReader, ReadFrameError};
+use resume::{parse_resume_command, since_from_path, InvalidSince};
+use room::{room_from_path, Membership, NicknameTaken, Rooms};
+use std::sync::Arc;
+use subprotocol::Subprotocol;
+use tls::{load_tls_acceptor, BoxedStream};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::TlsAcceptor;
+use websocket_codec::{
+    close::ParseCloseFrameError,
+    deflate::{Deflater, Inflater},
+    fragment, AssembleError, CloseCode, CloseFrame, DecodeError, Decoder, Frame, Message,
+    MessageAssembler, Opcode, Role,
+};
+
+/// 연결을 받아서 채팅 서버를 돌립니다. 리스너가 망가지지 않는 한 끝나지 않아요.
+///
+/// 테스트는 127.0.0.1:0에 리스너를 열어서 넘겨줍니다.
+/// 설정이 틀렸거나, `config.tls`가 있는데 인증서를 못 읽으면 바로 에러를 돌려줘요.
+pub async fn serve(tcp_listener: TcpListener, db: Db, config: Config) -> Result<()> {
+    serve_with_shutdown(tcp_listener, db, config, std::future::pending()).await
+}
+
+/*
+    그냥 프로세스를 죽이면
+    - 클라이언트는 Close frame 없이 연결이 끊겨서(1006) 서버가 죽었는지 네트워크가 끊겼는지 몰라요.
+    - 받아서 DB에 쓰던 메시지가 날아갈 수 있어요.
+    그래서 `shutdown`이 끝나면(main에서는 SIGINT, SIGTERM)
+    1. 새 연결을 더 받지 않고
+    2. 모든 유저에게 1001(Going Away)로 Close를 보내요. 그 전에 쌓여있던 메시지는 먼저 보내줍니다.
+    3. 유저들의 연결이 다 정리되길 shutdown_timeout까지만 기다리고
+    4. DB를 닫아요.
+*/
+/// `shutdown`이 끝나면 위 순서대로 정리하고 돌아와요. main은 이걸 불러요.
+pub async fn serve_with_shutdown(
+    tcp_listener: TcpListener,
+    db: Db,
+    config: Config,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<()> {
+    config.validate()?;
+    let tls_acceptor = config.tls.as_ref().map(load_tls_acceptor).transpose()?;
+    let db = Arc::new(db);
+    let config = Arc::new(config);
+
+    let rooms = Arc::new(Rooms::default());
+    // Arc 쓰는 이유: 언제 힙에서 제거해야하는지 알기 위해서!
+
+    // 끝날 때 기다려야 하니 유저마다 띄운 task를 모아둬요.
+    let mut user_tasks = tokio::task::JoinSet::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let tcp_stream = tokio::select! {
+            accepted = tcp_listener.accept() => accepted?.0,
+            // 끝난 task는 그때그때 치워줘야 JoinSet이 계속 커지지 않아요.
+            Some(_) = user_tasks.join_next() => continue,
+            _ = &mut shutdown => break,
+        };
+        let id = generate_new_id();
+
+        // 어느 방에 들어갈지는 HTTP 요청을 읽어봐야 알아요. 들어가는 건 user_loop에서 해요.
+        user_tasks.spawn(user_task(
+            tcp_stream,
+            tls_acceptor.clone(),
+            id,
+            rooms.clone(),
+            db.clone(),
+            config.clone(),
+        ));
+    }
+
+    // Q. 유저 5천명 들어오면, 스레드 몇개? 5천개
+
+    drop(tcp_listener);
+    println!("Shutting down: closing {} connections", user_tasks.len());
+
+    // 목록에서 꺼내 가니, 이제부터 오는 메시지는 아무에게도 뿌리지 않아요.
+    rooms.close_all(CloseFrame::new(
+        CloseCode::GOING_AWAY,
+        "Server is shutting down",
+    ));
+
+    let drained = tokio::time::timeout(config.shutdown_timeout, async {
+        while user_tasks.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        println!(
+            "Shutting down: {} connections did not close in time",
+            user_tasks.len()
+        );
+        user_tasks.shutdown().await;
+    }
+
+    db.close().await;
+
+    Ok(())
+}
+
+/*
+    연결을 받으면
+    스레드를 만들자!
+    그럼 그 스레드 안에선 뭘 해요?
+    연결된 Tcp Socket에 대한 WebSocket Handshake를 하구요,
+    메시지를 받고요,
+    다른 사람들에게 뿌려줘요.
+
+    유저 A에 대한 쓰레드
+    - 유저 A의 메시지를 받는중
+       - Timeout을 걸어서, 받는데 기다리는 최대 시간을 정하자.
+       // - Nonblocking 함수로 처리하자! << 이거는 잠시 머리속에서 빼놓자.
+    유저 B에 대한 쓰레드
+     - 이미 유저 B의 메시지 받았고, 그것을 다른 유저들에게 쏴줘야함.
+
+    스레드는 언제까지 돌아야해? 언제 꺼져야해?
+*/
+async fn user_task(
+    tcp_stream: TcpStream,
+    tls_acceptor: Option<TlsAcceptor>,
+    my_id: u64,
+    rooms: Arc<Rooms>,
+    db: Arc<Db>,
+    config: Arc<Config>,
+) {
+    // TLS 핸드셰이크도 여기서 해요. accept 루프에서 하면 느린 클라이언트 하나가 다른 사람들의 연결을 막아요.
+    match accept_stream(tcp_stream, tls_acceptor, &config).await {
+        Ok(stream) => {
+            let _ = user_loop(stream, my_id, rooms, db, config).await;
+        }
+        Err(error) => {
+            println!("user {my_id}: {error}");
+        }
+    }
+}
+
+/// TLS를 쓰면 TLS 핸드셰이크를 마친 스트림을, 아니면 TCP 스트림을 그대로 돌려줘요.
+async fn accept_stream(
+    tcp_stream: TcpStream,
+    tls_acceptor: Option<TlsAcceptor>,
+    config: &Config,
+) -> Result<BoxedStream> {
+    let Some(tls_acceptor) = tls_acceptor else {
+        return Ok(Box::new(tcp_stream));
+    };
+
+    // HTTP 헤더와 마찬가지로, 핸드셰이크를 끝없이 끄는 클라이언트는 기다려주지 않아요.
+    let tls_stream = tokio::time::timeout(
+        config.http_request_limits.header_read_timeout,
+        tls_acceptor.accept(tcp_stream),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("TLS handshake timed out"))?
+    .map_err(|error| anyhow::anyhow!("TLS handshake failed: {error}"))?;
+
+    Ok(Box::new(tls_stream))
+}
+
+async fn user_loop(
+    stream: BoxedStream,
+    my_id: u64,
+    rooms: Arc<Rooms>,
+    db: Arc<Db>,
+    config: Arc<Config>,
+) -> Result<()> {
+    /*
+        읽기는 처음부터 BufReader로 합니다.
+        HTTP 헤더를 한 줄씩 읽으려면 필요하고, 그 뒤에 웹소켓 프레임을 읽을 때도
+        BufReader에 이미 읽혀있는 바이트를 잃어버리면 안되니까 계속 같은 걸 써요.
+    */
+    let (tcp_read, mut tcp_write) = tokio::io::split(stream);
+    let mut tcp_read = BufReader::new(tcp_read);
+
+    let request = match receive_http_request(&mut tcp_read, &config.http_request_limits).await {
+        Ok(request) => request,
+        Err(error) => {
+            println!("user {my_id}: Invalid http request: {error}");
+            if let Some(response) = error.to_response() {
+                tcp_write.write_all(response.as_bytes()).await?;
+            }
+            tcp_write.shutdown().await?;
+            return Ok(());
+        }
+    };
+
+    if !request.is_websocket_upgrade_request() {
+        handle_non_websocket_http_request(&mut tcp_write, request, &db, &rooms, &config).await?;
+        // TLS라면 close_notify까지 보내야 상대가 응답이 잘리지 않았다는 걸 알아요.
+        tcp_write.shutdown().await?;
+        return Ok(());
+    }
+
+    if let Err(error) = validate_websocket_upgrade_request(&request) {
+        println!("user {my_id}: Invalid websocket handshake {error:?}");
+        tcp_write.write_all(error.to_response().as_bytes()).await?;
+        tcp_write.shutdown().await?;
+        return Ok(());
+    }
+
+    // 형식은 맞아도 다른 사이트의 페이지에서 온 연결이면 받지 않아요.
+    if !is_allowed_origin(&request, &config.allowed_origins) {
+        println!(
+            "user {my_id}: Origin not allowed {:?}",
+            request.header_values("Origin").collect::<Vec<_>>()
+        );
+        tcp_write
+            .write_all(HandshakeError::ForbiddenOrigin.to_response().as_bytes())
+            .await?;
+        tcp_write.shutdown().await?;
+        return Ok(());
+    }
+
+    let Some(room) = room_from_path(&request.path) else {
+        println!("user {my_id}: No room at {}", request.path);
+        tcp_write
+            .write_all(http_response("404 Not Found", &[], "No such room").as_bytes())
+            .await?;
+        tcp_write.shutdown().await?;
+        return Ok(());
+    };
+    let Ok(nickname) = nickname_from_path(&request.path) else {
+        println!("user {my_id}: Invalid nickname in {}", request.path);
+        tcp_write
+            .write_all(http_response("400 Bad Request", &[], "Invalid nickname").as_bytes())
+            .await?;
+        tcp_write.shutdown().await?;
+        return Ok(());
+    };
+    let Ok(since) = since_from_path(&request.path) else {
+        println!("user {my_id}: Invalid since in {}", request.path);
+        tcp_write
+            .write_all(http_response("400 Bad Request", &[], "Invalid since").as_bytes())
+            .await?;
+        tcp_write.shutdown().await?;
+        return Ok(());
+    };
+    // 101을 보내기 전에 들어가야, 그 사이에 온 메시지도 받아요.
+    let Ok(membership) = rooms.join(
+        room,
+        my_id,
+        nickname,
+        Arc::new(Outbox::new(
+            config.channel_capacity,
+            config.slow_consumer_policy,
+        )),
+    ) else {
+        println!("user {my_id}: Nickname {nickname:?} is taken in {room}");
+        tcp_write
+            .write_all(http_response("409 Conflict", &[], "Nickname taken").as_bytes())
+            .await?;
+        tcp_write.shutdown().await?;
+        return Ok(());
+    };
+    // 101보다 먼저 정해둬야 DB에서 읽은 것과 새로 온 것의 순서가 맞아요.
+    if let Some(since) = since {
+        // 아직 없는 id까지 받았다고 하면, 그 id까지의 새 메시지를 이미 보낸 걸로 알고 버려요. 그래서 줄여요.
+        let since = since.min(db.last_message_id().await?);
+        membership.outbox.resume(since, None);
+    }
+    let membership = Arc::new(membership);
+
+    let negotiated =
+        send_websocket_upgrade_response(&mut tcp_write, &request, config.permessage_deflate)
+            .await?;
+
+    if let Some(subprotocol) = negotiated.subprotocol {
+        println!("user {my_id}: speaks {}", subprotocol.name());
+    }
+    // 압축 상태는 연결마다 따로 가지고 있어야 해요. (context takeover)
+    let mut receive_session = ReceiveSession {
+        reader: FrameReader::new(
+            tcp_read,
+            Decoder::new(Role::Server)
+                .with_max_frame_size(config.max_inbound_frame_size)
+                .allow_rsv1(negotiated.deflate.is_some()),
+            config.message_read_timeout,
+        ),
+        assembler: MessageAssembler::new(
+            negotiated
+                .deflate
+                .as_ref()
+                .map(|deflate| Inflater::new(deflate, Role::Server)),
+            config.max_inbound_message_size,
+        ),
+        subprotocol: negotiated.subprotocol,
+    };
+    let mut send_session = SendSession {
+        deflater: negotiated
+            .deflate
+            .as_ref()
+            .map(|deflate| Deflater::new(deflate, Role::Server)),
+        subprotocol: negotiated.subprotocol,
+    };
+
+    let (control_tx, mut control_rx) = tokio::sync::mpsc::channel(16);
+    let (stop_tx, mut stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let send_db = db.clone();
+    let send_membership = membership.clone();
+    let mut recv_task = tokio::spawn(async move {
+        let mut stopped = false;
+        // `?since=`로 이미 부탁했으면 첫 메시지도 평소처럼 다뤄요.
+        let mut resumable = since.is_none();
+        loop {
+            // 읽는 중에 멈춰달라고 하면 바로 멈춥니다. 받다 만 프레임은 FrameReader에 그대로 남아요.
+            let result = tokio::select! {
+                message = receive_user_message(&mut receive_session, &control_tx) => message,
+                _ = &mut stop_rx => {
+                    stopped = true;
+                    break;
+                }
+            };
+
+            let result = match result {
+                Ok(message) => {
+                    let request = parse_user_request(message, receive_session.subprotocol);
+                    // 이어받기는 첫 메시지로만 부탁할 수 있어요. 그 전에 뭘 보냈는지는 send task가 알아요.
+                    match (std::mem::take(&mut resumable), &request) {
+                        (true, Ok(UserRequest::Resume(since))) => {
+                            let _ = control_tx.send(Control::Resume(*since)).await;
+                            Ok(())
+                        }
+                        _ => handle_user_request(request, &membership, &db).await,
+                    }
+                }
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(_) => {
+                    println!("user {my_id} send Message");
+                }
+                Err(error) => {
+                    println!("user {my_id}: {error}");
+
+                    // 클라이언트가 먼저 Close를 보냈으면 그대로 돌려주고,
+                    // 우리가 뭔가 잘못된걸 발견했으면 그에 맞는 코드로 Close를 보냅니다.
+                    let _ = control_tx.send(Control::Close(error.close_frame())).await;
+                    break;
+                }
+            }
+        }
+        (receive_session.reader.into_inner(), stopped)
+    });
+
+    let send_task = tokio::spawn(async move {
+        let close_sent = match send_other_users_messages_to_user(
+            &mut tcp_write,
+            &send_membership,
+            &mut control_rx,
+            &mut send_session,
+            &send_db,
+            &config,
+        )
+        .await
+        {
+            Ok(close_sent) => close_sent,
+            Err(error) => {
+                println!("user {my_id}: {error}");
+                false
+            }
+        };
+        (tcp_write, config, close_sent)
+    });
+
+    let _abort_tasks = AbortOnDrop(vec![recv_task.abort_handle(), send_task.abort_handle()]);
+
+    /*
+        send task는 recv task가 Close를 부탁해야 끝나요.
+        반대로 send task가 먼저 끝났다면(Pong이 안온다거나, 쓰기가 실패했다거나)
+        recv task는 영영 안올 메시지를 기다리고 있을테니 멈춰달라고 합니다.
+        abort와 달리 읽던 스트림을 망가뜨리지 않고 돌려받을 수 있어요.
+    */
+    let (mut tcp_write, config, close_sent) = send_task.await.unwrap();
+    // 서버가 꺼지거나 너무 느려서 우리가 먼저 Close를 보냈다면, 상대의 Close를 close_timeout까지는 기다려줘요.
+    let received = if close_sent {
+        tokio::time::timeout(config.close_timeout, &mut recv_task)
+            .await
+            .ok()
+    } else {
+        None
+    };
+    let (mut tcp_read, stopped) = match received {
+        Some(result) => result.unwrap(),
+        None => {
+            let _ = stop_tx.send(());
+            recv_task.await.unwrap()
+        }
+    };
+    if stopped {
+        return Ok(());
+    }
+
+    /*
+        Close를 주고받았으면 이제 TCP를 닫을 차례.
+        그런데 아직 읽지 않은 데이터가 남은 채로 소켓을 닫으면 OS가 RST를 보내버려서
+        방금 보낸 Close frame이 상대에게 도착하지 못할 수도 있어요.
+        그러니 쓰기 쪽만 먼저 닫고(FIN), 상대가 닫을 때까지 남은 데이터를 읽어서 버립니다.
+    */
+    tcp_write.shutdown().await?;
+    let _ = tokio::time::timeout(config.close_timeout, async {
+        let mut buf = [0u8; 1024];
+        while let Ok(1..) = tcp_read.read(&mut buf).await {}
+    })
+    .await;
+
+    Ok(())
+}
+
+async fn handle_non_websocket_http_request(
+    tcp_stream: &mut WriteHalf<BoxedStream>,
+    request: HttpRequest,
+    db: &Db,
+    rooms: &Rooms,
+    config: &Config,
+) -> Result<()> {
+    let cors_headers = cors_headers(&request, &config.allowed_origins);
+    let cors_header_lines = cors_headers
+        .iter()
+        .map(|(key, value)| format!("{key}: {value}\r\n"))
+        .collect::<String>();
+
+    let path = request
+        .path
+        .split_once('?')
+        .map_or(request.path.as_str(), |(path, _)| path);
+    // `/`와 `/rooms/{name}`은 그 방의 채팅 페이지, `/rooms/{name}/users`는 그 방에 있는 사람들이에요.
+    let users_room = path.strip_suffix("/users").and_then(room_from_path);
+    match (
+        request.method.as_str(),
+        path,
+        room_from_path(path),
+        users_room,
+    ) {
+        ("GET", _, Some(room), _) => {
+            let mut messages = db.list_messages(room, config.history_size as i64).await?;
+            // 페이지에 담은 가장 최근 메시지. 연결할 때 이 뒤부터 보내달라고 해요. (resume.rs)
+            let last_id = messages.first().map_or(0, |delivery| delivery.id);
+            messages.reverse();
+
+            let message_lis = messages
+                .into_iter()
+                .map(|delivery| {
+                    // 손님이 보낸 메시지는 예전처럼 내용만 보여줘요.
+                    let author = delivery
+                        .author
+                        .map_or(String::new(), |author| format!("{author}: "));
+                    match delivery.message {
+                        Message::Text(text) => format!("<li>{author}{text}</li>"),
+                        Message::Binary(bytes) => {
+                            format!("<li>{author}[binary {} bytes]</li>", bytes.len())
+                        }
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            /*
+                예전에 있던 버그
+                1. DB에서 메시지를 긁어다가 사용자에게 보내줄 것.
+                2. 근데 그 사이에 다른 유저가 메시지를 보냄.
+                3. 하지만 이 사용자는 WebSocket을 연결하기 전인걸?
+                4. 그러면 이 사용자는 html을 받고, WebSocket을 연결하기 전에 생긴 새로운 메시지들은 못받겠네?
+                그래서 페이지에 담은 마지막 id를 `?since=`로 넘겨서, 그 뒤의 메시지부터 받아요.
+            */
+
+            let index_html = format!(
+                "
+            <html>
+                <head>
+                    <title>Chat - {room}</title>
+                    <meta charset=\"utf-8\">
+                </head>
+                <body>
+                    <h1>{room}</h1>
+                    <input id=\"input\" type=\"text\"/>
+                    <ul id=\"messages\">
+                        {message_lis}
+                    </ul>
+
+                    <script>
+                        const input = document.getElementById('input');
+                        const messages = document.getElementById('messages');
+                        // https로 받은 페이지에서는 ws://로 연결할 수 없어요. (mixed content)
+                        const scheme = location.protocol === 'https:' ? 'wss' : 'ws';
+                        // 페이지와 같은 경로로 연결하면 같은 방에 들어가요. `?nick=`도 그대로 넘겨요.
+                        // 페이지를 받은 뒤에 온 메시지도 받도록, 페이지에 있는 마지막 메시지 id를 알려줘요.
+                        const params = new URLSearchParams(location.search);
+                        params.set('since', '{last_id}');
+                        const ws = new WebSocket(`${{scheme}}://${{location.host}}${{location.pathname}}?${{params}}`);
+
+                        ws.addEventListener('message', (event) => {{
+                            const message = event.data;
+                            addMessageToList(message);
+                        }});
+
+                        input.addEventListener('keydown', (event) => {{
+                            if (event.key === 'Enter') {{
+                                const message = input.value;
+                                input.value = '';
+
+                                ws.send(message);
+                                // `/nick` 같은 명령은 서버가 대답해줘요.
+                                if (!message.startsWith('/')) {{
+                                    addMessageToList(message);
+                                }}
+                            }}
+                        }});
+
+                        function addMessageToList(message) {{
+                            const li = document.createElement('li');
+                            li.innerText = message;
+                            messages.appendChild(li);
+                        }}
+                    </script>
+                </body>
+            </html>
+            "
+            );
+
+            tcp_stream
+                .write_all(
+                    format!("HTTP/1.1 200 OK\r\n{cors_header_lines}\r\n{}", index_html).as_bytes(),
+                )
+                .await?;
+        }
+        ("GET", _, _, Some(room)) => {
+            let body = serde_json::to_string(&rooms.users(room))?;
+            tcp_stream
+                .write_all(
+                    http_response_with_content_type(
+                        "200 OK",
+                        "application/json",
+                        &cors_headers,
+                        &body,
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+        }
+        ("GET", "/rooms", _, _) => {
+            let body = serde_json::to_string(&rooms.list())?;
+            tcp_stream
+                .write_all(
+                    http_response_with_content_type(
+                        "200 OK",
+                        "application/json",
+                        &cors_headers,
+                        &body,
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+        }
+        ("GET", "/metrics", _, _) => {
+            tcp_stream
+                .write_all(http_response("200 OK", &cors_headers, &metrics::render()).as_bytes())
+                .await?;
+        }
+        // 브라우저가 다른 사이트에서 요청하기 전에 먼저 물어보는 preflight
+        ("OPTIONS", _, _, _) if !cors_headers.is_empty() => {
+            let headers = [cors_headers.as_slice(), PREFLIGHT_HEADERS].concat();
+            tcp_stream
+                .write_all(http_response("204 No Content", &headers, "").as_bytes())
+                .await?;
+        }
+        ("OPTIONS", _, _, _) if request.header_values("Origin").next().is_some() => {
+            tcp_stream
+                .write_all(HandshakeError::ForbiddenOrigin.to_response().as_bytes())
+                .await?;
+        }
+        _ => {
+            tcp_stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\n\r\n")
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 서버가 꺼지거나 너무 느려서 우리가 먼저 Close를 보내고 끝났으면 true.
+async fn send_other_users_messages_to_user(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    membership: &Membership,
+    control_rx: &mut tokio::sync::mpsc::Receiver<Control>,
+    session: &mut SendSession,
+    db: &Db,
+    config: &Config,
+) -> Result<bool> {
+    let my_id = membership.id;
+    let outbox = &membership.outbox;
+    // mpsc = multiple producer, single consumer queue
+
+    /*
+    다른 유저들이 보낸 메시지를 받아서
+    내가 연결된 유저에게 보내주자.
+
+    Q. 다른 유저들이 보낸 메시지를 내가 어떻게 받아?
+    */
+
+    // 클로즈 되었거나, 새 메시지를 받거나!
+
+    /*
+        TCP는 상대가 말없이 사라져도(랜선 뽑기, 와이파이 끊김) 우리가 알 방법이 없어요.
+        그래서 주기적으로 Ping을 보내보고, Pong이 제때 안오면 끊어버립니다.
+    */
+    let mut ping_interval = tokio::time::interval(config.ping_interval);
+    ping_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ping_interval.tick().await; // 첫 tick은 바로 끝나니까 버립니다.
+    let mut pong_deadline: Option<tokio::time::Instant> = None;
+    // 이 유저에게 마지막으로 보낸 메시지의 DB id. 메시지는 id 순서대로 뿌려지니 이것보다 작으면 이미 보낸 거예요.
+    let mut last_sent_id = 0;
+    // 처음 보낸 메시지의 DB id. 처음부터 지금까지 보낸 건 이것과 last_sent_id 사이에 다 있어요.
+    // 이어받기를 부탁받았을 때 이미 보낸 걸 다시 보내지 않으려고 기억해요.
+    // 그 사이에 버려서 못 보낸 게 있으면(DropOldest) Outbox::resume이 이 범위를 쓰지 않아요.
+    let mut first_sent_id: Option<i64> = None;
+
+    loop {
+        tokio::select! {
+            outgoing = outbox.recv() => match outgoing {
+                Outgoing::Message(delivery) => {
+                    // DB에서 다시 읽어 보낸 것과 겹칠 수 있어요.
+                    if delivery.id <= last_sent_id {
+                        continue;
+                    }
+                    write_delivery(tcp_write, &delivery, membership, session, config).await?;
+                    first_sent_id.get_or_insert(delivery.id);
+                    last_sent_id = delivery.id;
+                }
+                Outgoing::Notice(notice) => {
+                    write_system_text(
+                        tcp_write,
+                        EnvelopeType::Notice,
+                        &notice,
+                        membership,
+                        session,
+                        config,
+                    )
+                    .await?;
+                }
+                Outgoing::Error(reason) => {
+                    write_system_text(
+                        tcp_write,
+                        EnvelopeType::Error,
+                        &reason,
+                        membership,
+                        session,
+                        config,
+                    )
+                    .await?;
+                }
+                Outgoing::Replay { after_id } => {
+                    last_sent_id = last_sent_id.max(after_id);
+                    first_sent_id.get_or_insert(last_sent_id + 1);
+                    let replayed =
+                        replay_from_db(tcp_write, membership, db, last_sent_id, session, config)
+                            .await?;
+                    last_sent_id = replayed.unwrap_or(last_sent_id);
+                    if replayed.is_some() {
+                        // 한 번에 너무 오래 붙잡고 있으면 Ping이나 Close를 못 보내니 나눠서 해요.
+                        outbox.continue_replay();
+                    } else {
+                        outbox.stop_lagging();
+                        // stop_lagging 직전에 저장된 건 큐에도 없어요. 한 번 더 읽어요.
+                        while let Some(id) =
+                            replay_from_db(tcp_write, membership, db, last_sent_id, session, config)
+                                .await?
+                        {
+                            last_sent_id = id;
+                        }
+                        outbox.finish_replay();
+                    }
+                }
+                Outgoing::Close(close_frame) => {
+                    println!("user {my_id}: Closing {:?}", close_frame.code);
+                    write_control_frame(tcp_write, Opcode::Close, close_frame.to_payload()).await?;
+                    return Ok(true);
+                }
+            },
+            control = control_rx.recv() => match control {
+                Some(Control::Pong(payload)) => {
+                    write_control_frame(tcp_write, Opcode::Pong, payload).await?;
+                }
+                Some(Control::PongReceived) => {
+                    pong_deadline = None;
+                }
+                Some(Control::Resume(since)) => {
+                    let since = since.min(db.last_message_id().await?);
+                    outbox.resume(since, first_sent_id.map(|first| first..=last_sent_id));
+                    last_sent_id = since;
+                }
+                Some(Control::Close(close_frame)) => {
+                    println!("user {my_id}: Connection Closed");
+                    // Close를 보낸 뒤로는 아무것도 보내면 안돼요.
+                    if let Some(close_frame) = close_frame {
+                        write_control_frame(tcp_write, Opcode::Close, close_frame.to_payload()).await?;
+                    }
+                    break;
+                }
+                None => break,
+            },
+            _ = ping_interval.tick(), if pong_deadline.is_none() => {
+                write_control_frame(tcp_write, Opcode::Ping, vec![]).await?;
+                pong_deadline = Some(tokio::time::Instant::now() + config.pong_timeout);
+            }
+            _ = tokio::time::sleep_until(pong_deadline.unwrap_or_else(tokio::time::Instant::now)),
+                if pong_deadline.is_some() =>
+            {
+                return Err(anyhow::anyhow!("Pong not received in time"));
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// DB에서 한 번에 읽어서 보낼 메시지 개수.
+const REPLAY_BATCH_SIZE: i64 = 256;
+
+/// 이 유저의 방에서 `after_id` 뒤의 메시지를 REPLAY_BATCH_SIZE개까지 보내요. 이 유저가 보냈거나 이미 받은 건 빼고요.
+/// 마지막으로 읽은 id를 돌려주고, 더 읽을 게 없었으면 None.
+async fn replay_from_db(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    membership: &Membership,
+    db: &Db,
+    after_id: i64,
+    session: &mut SendSession,
+    config: &Config,
+) -> Result<Option<i64>> {
+    let mut last_read_id = None;
+    for delivery in db
+        .list_messages_after(&membership.room, after_id, REPLAY_BATCH_SIZE)
+        .await?
+    {
+        last_read_id = Some(delivery.id);
+        if membership.outbox.skips_replay(delivery.id) {
+            continue;
+        }
+        write_delivery(tcp_write, &delivery, membership, session, config).await?;
+    }
+
+    Ok(last_read_id)
+}
+
+/// chat.v2면 봉투에 넣어서, 아니면 예전처럼 내용만 보내요.
+async fn write_delivery(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    delivery: &Delivery,
+    membership: &Membership,
+    session: &mut SendSession,
+    config: &Config,
+) -> Result<()> {
+    match session.subprotocol {
+        Some(Subprotocol::ChatV2) => {
+            write_text_message(
+                tcp_write,
+                &envelope::encode_delivery(delivery, &membership.room),
+                &mut session.deflater,
+                config.max_outbound_frame_size,
+            )
+            .await
+        }
+        _ => {
+            write_message(
+                tcp_write,
+                &delivery.message,
+                &mut session.deflater,
+                config.max_outbound_frame_size,
+            )
+            .await
+        }
+    }
+}
+
+/// 알림이나 에러. chat.v2가 아니면 글자만 보내요.
+async fn write_system_text(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    kind: EnvelopeType,
+    text: &str,
+    membership: &Membership,
+    session: &mut SendSession,
+    config: &Config,
+) -> Result<()> {
+    let text = match session.subprotocol {
+        Some(Subprotocol::ChatV2) => envelope::encode_text(kind, &membership.room, text),
+        _ => text.to_string(),
+    };
+    write_text_message(
+        tcp_write,
+        &text,
+        &mut session.deflater,
+        config.max_outbound_frame_size,
+    )
+    .await
+}
+
+/// 핸드셰이크에서 정해진, 연결마다 다른 것들 중 받는 쪽에서 쓰는 것.
+struct ReceiveSession {
+    reader: FrameReader<BufReader<ReadHalf<BoxedStream>>>,
+    assembler: MessageAssembler,
+    subprotocol: Option<Subprotocol>,
+}
+
+/// 핸드셰이크에서 정해진, 연결마다 다른 것들 중 보내는 쪽에서 쓰는 것.
+struct SendSession {
+    deflater: Option<Deflater>,
+    subprotocol: Option<Subprotocol>,
+}
+
+/*
+    서버를 끌 때 shutdown_timeout이 지나면 user task를 abort해요. 그런데 그 안에서 tokio::spawn한 task들은
+    JoinHandle을 drop해도 계속 돌아요. 그러면 DB를 닫은 뒤에도 recv task가 메시지를 저장하려 하거나,
+    send task가 읽지 않는 클라이언트에게 쓰느라 계속 붙잡혀 있을 수 있어요.
+    그래서 user task가 어떻게 끝나든 같이 멈추게 해요. 이미 끝난 task를 abort하면 아무 일도 없어요.
+*/
+struct AbortOnDrop(Vec<tokio::task::AbortHandle>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+/// recv task가 받은 control frame 중에서 send task가 처리해야 하는 것들.
+/// 소켓에 쓰는 건 send task만 하니까 부탁해야 해요.
+enum Control {
+    /// 클라이언트가 보낸 Ping에 그대로 돌려줄 payload.
+    Pong(Vec<u8>),
+    /// 우리가 보낸 Ping에 클라이언트가 대답했어요.
+    PongReceived,
+    /// 더 받을 게 없으니 연결을 닫아주세요. 보낼 Close frame이 없으면 None.
+    Close(Option<CloseFrame>),
+    /// 클라이언트가 첫 메시지로 이 id 뒤부터 보내달래요.
+    Resume(i64),
+}
+
+#[derive(Debug)]
+enum ReceiveUserMessageError {
+    Io(std::io::Error),
+    ProtocolError(String),
+    InvalidData(String),
+    /// 지금 쓰는 subprotocol에서는 받을 수 없는 종류의 메시지예요.
+    UnsupportedData(String),
+    /// 설정한 크기 제한을 넘었어요.
+    MessageTooBig(String),
+    /// 메시지를 보내기 시작해놓고 제시간에 끝내지 않았어요.
+    Timeout,
+    /// 클라이언트가 Close frame을 보냈어요.
+    Disconnected(CloseFrame),
+    FailToSaveMessageToDb,
+}
+
+impl std::fmt::Display for ReceiveUserMessageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiveUserMessageError::Io(error) => write!(f, "IO error: {error}"),
+            ReceiveUserMessageError::ProtocolError(reason) => {
+                write!(f, "Protocol error: {reason}")
+            }
+            ReceiveUserMessageError::InvalidData(reason) => write!(f, "Invalid data: {reason}"),
+            ReceiveUserMessageError::UnsupportedData(reason) => {
+                write!(f, "Unsupported data: {reason}")
+            }
+            ReceiveUserMessageError::MessageTooBig(reason) => {
+                write!(f, "Message too big: {reason}")
+            }
+            ReceiveUserMessageError::Timeout => write!(f, "Message not received in time"),
+            ReceiveUserMessageError::Disconnected(close_frame) => {
+                write!(f, "Disconnected: {close_frame:?}")
+            }
+            ReceiveUserMessageError::FailToSaveMessageToDb => {
+                write!(f, "Fail to save message to db")
+            }
+        }
+    }
+}
+
+impl ReceiveUserMessageError {
+    fn from_read_frame_error(error: ReadFrameError) -> Self {
+        match error {
+            ReadFrameError::Io(error) => ReceiveUserMessageError::Io(error),
+            ReadFrameError::Decode(error) => Self::from_decode_error(error),
+            ReadFrameError::Timeout => ReceiveUserMessageError::Timeout,
+        }
+    }
+
+    fn from_decode_error(error: DecodeError) -> Self {
+        match error {
+            DecodeError::FrameTooBig { .. } => {
+                metrics::count(&metrics::OVERSIZED_FRAMES);
+                ReceiveUserMessageError::MessageTooBig(error.to_string())
+            }
+            _ => ReceiveUserMessageError::ProtocolError(error.to_string()),
+        }
+    }
+
+    fn from_assemble_error(error: AssembleError) -> Self {
+        match error {
+            AssembleError::Protocol(reason) => {
+                ReceiveUserMessageError::ProtocolError(reason.to_string())
+            }
+            AssembleError::InvalidUtf8 | AssembleError::InvalidCompressedData => {
+                ReceiveUserMessageError::InvalidData(error.to_string())
+            }
+            AssembleError::MessageTooBig { .. } => {
+                metrics::count(&metrics::OVERSIZED_MESSAGES);
+                ReceiveUserMessageError::MessageTooBig(error.to_string())
+            }
+        }
+    }
+
+    /// 이 에러로 연결을 닫을 때 클라이언트에게 보낼 Close frame.
+    fn close_frame(&self) -> Option<CloseFrame> {
+        match self {
+            // 소켓이 이미 망가졌으니 보낼 수가 없어요. (1006 Abnormal Closure)
+            ReceiveUserMessageError::Io(_) => None,
+            ReceiveUserMessageError::ProtocolError(reason) => {
+                Some(CloseFrame::new(CloseCode::PROTOCOL_ERROR, reason.as_str()))
+            }
+            ReceiveUserMessageError::InvalidData(reason) => {
+                Some(CloseFrame::new(CloseCode::INVALID_DATA, reason.as_str()))
+            }
+            ReceiveUserMessageError::UnsupportedData(reason) => Some(CloseFrame::new(
+                CloseCode::UNSUPPORTED_DATA,
+                reason.as_str(),
+            )),
+            ReceiveUserMessageError::MessageTooBig(reason) => {
+                Some(CloseFrame::new(CloseCode::MESSAGE_TOO_BIG, reason.as_str()))
+            }
+            ReceiveUserMessageError::Timeout => Some(CloseFrame::new(
+                CloseCode::POLICY_VIOLATION,
+                "Message not received in time",
+            )),
+            // 받은 코드를 그대로 돌려줍니다. 코드가 없었으면 우리도 빈 Close를 보내요.
+            ReceiveUserMessageError::Disconnected(close_frame) => Some(CloseFrame {
+                code: close_frame.code,
+                reason: String::new(),
+            }),
+            ReceiveUserMessageError::FailToSaveMessageToDb => Some(CloseFrame::new(
+                CloseCode::INTERNAL_ERROR,
+                "Fail to save message",
+            )),
+        }
+    }
+}
+
+/// 다음 메시지 하나를 받습니다. 사이에 끼어든 control frame은 여기서 처리해요.
+///
+/// 읽기는 취소돼도 괜찮아서 select!에 넣을 수 있어요. 받은 바이트는 전부 FrameReader가 들고 있습니다.
+async fn receive_user_message(
+    session: &mut ReceiveSession,
+    control_tx: &tokio::sync::mpsc::Sender<Control>,
+) -> Result<Message, ReceiveUserMessageError> {
+    loop {
+        let frame = session
+            .reader
+            .read_frame()
+            .await
+            .map_err(ReceiveUserMessageError::from_read_frame_error)?;
+
+        let message = match frame.opcode {
+            Opcode::Close => {
+                let close_frame =
+                    CloseFrame::parse(&frame.payload).map_err(|error| match error {
+                        ParseCloseFrameError::Protocol(reason) => {
+                            ReceiveUserMessageError::ProtocolError(reason)
+                        }
+                        ParseCloseFrameError::InvalidUtf8 => ReceiveUserMessageError::InvalidData(
+                            "Close reason is not valid UTF-8".to_string(),
+                        ),
+                    })?;
+                return Err(ReceiveUserMessageError::Disconnected(close_frame));
+            }
+            Opcode::Ping => {
+                let _ = control_tx.send(Control::Pong(frame.payload)).await;
+                None
+            }
+            Opcode::Pong => {
+                let _ = control_tx.send(Control::PongReceived).await;
+                None
+            }
+            _ => session
+                .assembler
+                .push(frame)
+                .map_err(ReceiveUserMessageError::from_assemble_error)?,
+        };
+
+        // 쪼개진 메시지 사이에 끼어든 control frame이 아니라면 메시지 하나가 끝난 거예요.
+        if !session.assembler.is_receiving() {
+            session.reader.finish_message();
+        }
+
+        // 아직 마지막 조각이 안왔으면 다음 프레임을 기다립시다.
+        let Some(message) = message else {
+            continue;
+        };
+
+        // 어떤 메시지를 받을 수 있는지는 핸드셰이크에서 고른 프로토콜마다 달라요.
+        return match (message, session.subprotocol) {
+            (
+                Message::Binary(_),
+                Some(subprotocol @ (Subprotocol::ChatV1 | Subprotocol::ChatV2)),
+            ) => Err(ReceiveUserMessageError::UnsupportedData(format!(
+                "{} only supports text messages",
+                subprotocol.name()
+            ))),
+            (message, _) => Ok(message),
+        };
+    }
+
+    // Q. 메시지가 오다가 중간에 말수도 있나요? 부분만 올 수 있나요?
+    // A. 네, 왜냐하면 TCP는 스트림이기 때문에, 메시지가 한번에 올 수도 있고, 여러번에 나눠서 올 수도 있습니다.
+
+    // Q. 그러면 내가 메시지 일부를 받아버렸음. 메시지를 파싱하다가 다 안와서 실패함. 그럼 함수를 끝낼건데, 그럼..... 읽었떤 메시지 어디로 감?
+    // A. 예전엔 사라졌어요. 함수의 스택 프레임이 사라지면서, 그 안에 있던 변수들도 사라지기 때문입니다.
+    //    지금은 읽은 바이트를 전부 FrameReader에 넣어두니까, 함수가 끝나도 FrameReader가 들고 있어요.
+}
+
+/// 유저가 보낸 메시지를 subprotocol에 맞게 읽은 것.
+enum UserRequest {
+    /// 저장하고 같은 방 사람들에게 뿌려요.
+    Message(Message),
+    /// 닉네임 바꾸기는 다른 사람에게 보내지 않고 여기서 처리해요.
+    Nick(String),
+    /// 이 id 뒤의 메시지부터 보내주세요. 첫 메시지일 때만 들어줘요. (resume.rs)
+    Resume(i64),
+}
+
+/// 잘못 보냈으면 유저에게 에러로 돌려줄 이유를 돌려줘요.
+fn parse_user_request(
+    message: Message,
+    subprotocol: Option<Subprotocol>,
+) -> Result<UserRequest, String> {
+    match (message, subprotocol) {
+        (Message::Text(text), Some(Subprotocol::ChatV2)) => Ok(match envelope::decode(&text)? {
+            Request::Message(body) => UserRequest::Message(Message::Text(body)),
+            Request::Nick(nickname) => UserRequest::Nick(nickname),
+            Request::Resume(since) => UserRequest::Resume(since),
+        }),
+        (Message::Text(text), _) => {
+            if let Some(nickname) = parse_nick_command(&text) {
+                return Ok(UserRequest::Nick(nickname.to_string()));
+            }
+            match parse_resume_command(&text) {
+                Some(Ok(since)) => Ok(UserRequest::Resume(since)),
+                Some(Err(InvalidSince)) => Err("* Usage: /resume <message id>".to_string()),
+                None => Ok(UserRequest::Message(Message::Text(text))),
+            }
+        }
+        (message, _) => Ok(UserRequest::Message(message)),
+    }
+}
+
+async fn handle_user_request(
+    request: Result<UserRequest, String>,
+    membership: &Membership,
+    db: &Db,
+) -> Result<(), ReceiveUserMessageError> {
+    match request {
+        Ok(UserRequest::Message(message)) => {
+            return save_and_send_to_other_users(message, membership, db).await
+        }
+        Ok(UserRequest::Nick(nickname)) => change_nickname(membership, &nickname),
+        // 첫 메시지였다면 recv task가 이미 send task에 넘겼어요.
+        Ok(UserRequest::Resume(_)) => {
+            membership
+                .outbox
+                .push_error("* Resume must be the first message".to_string());
+        }
+        // 잘못 보낸 건 연결을 끊을 만큼은 아니라서, 에러로 대답만 해요.
+        Err(reason) => {
+            membership.outbox.push_error(reason);
+        }
+    }
+    Ok(())
+}
+
+/// 잘 됐는지는 나에게만 알려줘요. 다른 사람들에게는 Rooms가 알려요.
+fn change_nickname(membership: &Membership, nickname: &str) {
+    if !is_valid_nickname(nickname) {
+        membership.outbox.push_error(format!(
+            "* Nickname must be 1-{MAX_NICKNAME_LENGTH} letters, digits, '-' or '_'"
+        ));
+        return;
+    }
+    match membership.rename(nickname) {
+        Ok(()) => membership
+            .outbox
+            .push_notice(format!("* You are now {nickname}")),
+        Err(NicknameTaken) => membership
+            .outbox
+            .push_error(format!("* Nickname {nickname} is taken")),
+    };
+}
+
+async fn save_and_send_to_other_users(
+    message: Message,
+    membership: &Membership,
+    db: &Db,
+) -> Result<(), ReceiveUserMessageError> {
+    // 저장한 순서(id)대로 뿌려지게 해요. 이유는 Membership::lock_message_order에 있어요.
+    let _message_order = membership.lock_message_order().await;
+    let author = membership.nickname();
+    let timestamp = unix_millis();
+    let id = db
+        .add_message(&membership.room, author.as_deref(), timestamp, &message)
+        .await
+        .map_err(|_| ReceiveUserMessageError::FailToSaveMessageToDb)?;
+    membership.broadcast(&Delivery {
+        id,
+        author,
+        timestamp: Some(timestamp),
+        message,
+    });
+
+    Ok(())
+}
+
+async fn write_message(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    message: &Message,
+    deflater: &mut Option<Deflater>,
+    max_frame_size: usize,
+) -> Result<()> {
+    match message {
+        Message::Text(text) => write_text_message(tcp_write, text, deflater, max_frame_size).await,
+        Message::Binary(bytes) => {
+            write_binary_message(tcp_write, bytes, deflater, max_frame_size).await
+        }
+    }
+}
+
+async fn write_text_message(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    message: &str,
+    deflater: &mut Option<Deflater>,
+    max_frame_size: usize,
+) -> Result<()> {
+    write_data_message(
+        tcp_write,
+        Opcode::Text,
+        message.as_bytes(),
+        deflater,
+        max_frame_size,
+    )
+    .await
+}
+
+async fn write_binary_message(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    message: &[u8],
+    deflater: &mut Option<Deflater>,
+    max_frame_size: usize,
+) -> Result<()> {
+    write_data_message(tcp_write, Opcode::Binary, message, deflater, max_frame_size).await
+}
+
+async fn write_data_message(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    opcode: Opcode,
+    payload: &[u8],
+    deflater: &mut Option<Deflater>,
+    max_frame_size: usize,
+) -> Result<()> {
+    match deflater {
+        Some(deflater) => {
+            let compressed = deflater.compress(payload)?;
+            write_fragmented_message(tcp_write, opcode, true, &compressed, max_frame_size).await
+        }
+        None => write_fragmented_message(tcp_write, opcode, false, payload, max_frame_size).await,
+    }
+}
+
+/// 너무 큰 메시지는 max_frame_size씩 잘라서 보냅니다. 어떻게 자르는지는 `fragment`에 있어요.
+async fn write_fragmented_message(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    opcode: Opcode,
+    compressed: bool,
+    payload: &[u8],
+    max_frame_size: usize,
+) -> Result<()> {
+    // 서버가 보내는 프레임은 마스킹하지 않아요.
+    for (header, chunk) in fragment(opcode, compressed, payload, max_frame_size) {
+        tcp_write.write_all(&header.encode()).await?;
+        tcp_write.write_all(chunk).await?;
+    }
+    // TLS는 암호화한 바이트를 안에 쥐고 있을 수 있어서, 다 보냈는지 flush로 확인해요.
+    tcp_write.flush().await?;
+
+    Ok(())
+}
+
+async fn write_control_frame(
+    tcp_write: &mut WriteHalf<BoxedStream>,
+    opcode: Opcode,
+    payload: Vec<u8>,
+) -> Result<()> {
+    tcp_write
+        .write_all(&Frame::new(opcode, payload).encode(None))
+        .await?;
+    tcp_write.flush().await?;
+
+    Ok(())
+}
+
+fn generate_new_id() -> u64 {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static NEXT_STREAM_ID: AtomicU64 = AtomicU64::new(0);
+
+    NEXT_STREAM_ID.fetch_add(1, Ordering::Relaxed)
+}
@@ -0,0 +1,6 @@
+/// 지금 시각(유닉스 밀리초). 메시지를 저장할 때 같이 적어요.
+pub(crate) fn unix_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/*
+    서버가 돌면서 세는 숫자들. `GET /metrics`로 볼 수 있어요.
+    generate_new_id처럼 어디서든 바로 더할 수 있게 static으로 둡니다.
+*/
+
+/// 프레임 하나가 max_inbound_frame_size보다 커서 1009로 끊은 횟수.
+pub(crate) static OVERSIZED_FRAMES: AtomicU64 = AtomicU64::new(0);
+/// 조각들을 합친(또는 압축을 푼) 메시지가 max_inbound_message_size보다 커서 1009로 끊은 횟수.
+pub(crate) static OVERSIZED_MESSAGES: AtomicU64 = AtomicU64::new(0);
+/// 느린 유저의 큐가 꽉 차서 가장 오래된 메시지를 버린 횟수. (drop-oldest)
+pub(crate) static SLOW_CONSUMER_DROPPED_MESSAGES: AtomicU64 = AtomicU64::new(0);
+/// 느린 유저의 큐가 꽉 차서 1008로 끊은 횟수. (disconnect)
+pub(crate) static SLOW_CONSUMER_DISCONNECTS: AtomicU64 = AtomicU64::new(0);
+/// 느린 유저의 큐가 꽉 차서 DB에서 다시 읽기 시작한 횟수. (replay-from-db)
+pub(crate) static SLOW_CONSUMER_REPLAYS: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn count(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn render() -> String {
+    [
+        ("websocket_oversized_frames_total", &OVERSIZED_FRAMES),
+        ("websocket_oversized_messages_total", &OVERSIZED_MESSAGES),
+        (
+            "websocket_slow_consumer_dropped_messages_total",
+            &SLOW_CONSUMER_DROPPED_MESSAGES,
+        ),
+        (
+            "websocket_slow_consumer_disconnects_total",
+            &SLOW_CONSUMER_DISCONNECTS,
+        ),
+        (
+            "websocket_slow_consumer_replays_total",
+            &SLOW_CONSUMER_REPLAYS,
+        ),
+    ]
+    .iter()
+    .map(|(name, counter)| format!("{name} {}\n", counter.load(Ordering::Relaxed)))
+    .collect()
+}
@@ -0,0 +1,77 @@
+use crate::handshake::query_value;
+
+/*
+    닉네임
+
+    업그레이드할 때 `?nick=alice`를 붙이거나, 연결한 뒤에 `/nick alice`를 보내서 정하고 바꿔요.
+    정하지 않은 유저는 손님이에요. 손님이 들어오고 나가는 건 알리지 않아요.
+    예전 클라이언트들은 닉네임을 모르니, 그 사람들이 받는 메시지는 예전과 똑같아요.
+
+    이름은 방 이름처럼 URL, HTML, JSON 어디에 넣어도 이스케이프할 필요 없는 글자로만 만들 수 있어요.
+    그래서 쿼리의 값도 퍼센트 디코딩 없이 그대로 써요.
+*/
+
+pub(crate) const MAX_NICKNAME_LENGTH: usize = 32;
+
+const NICK_COMMAND: &str = "/nick ";
+
+pub(crate) fn is_valid_nickname(nickname: &str) -> bool {
+    (1..=MAX_NICKNAME_LENGTH).contains(&nickname.len())
+        && nickname
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct InvalidNickname;
+
+/// 업그레이드 요청 경로의 `nick=` 값. 없으면 손님이라 None이고, 쓸 수 없는 이름이면 에러예요.
+pub(crate) fn nickname_from_path(path: &str) -> Result<Option<&str>, InvalidNickname> {
+    let Some(nickname) = query_value(path, "nick") else {
+        return Ok(None);
+    };
+
+    if is_valid_nickname(nickname) {
+        Ok(Some(nickname))
+    } else {
+        Err(InvalidNickname)
+    }
+}
+
+/// `/nick alice`면 `alice`. 쓸 수 있는 이름인지는 따로 봐요.
+pub(crate) fn parse_nick_command(text: &str) -> Option<&str> {
+    text.strip_prefix(NICK_COMMAND).map(str::trim)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nickname_from_path() {
+        assert_eq!(nickname_from_path("/"), Ok(None));
+        assert_eq!(nickname_from_path("/?since=3"), Ok(None));
+        assert_eq!(nickname_from_path("/?nick=alice"), Ok(Some("alice")));
+        assert_eq!(
+            nickname_from_path("/rooms/rust?since=3&nick=bob_2"),
+            Ok(Some("bob_2"))
+        );
+
+        for path in ["/?nick=", "/?nick=%EC%95%88", "/?nick=<b>", "/?nick=a b"] {
+            assert_eq!(nickname_from_path(path), Err(InvalidNickname), "{path}");
+        }
+        assert_eq!(
+            nickname_from_path(&format!("/?nick={}", "a".repeat(MAX_NICKNAME_LENGTH + 1))),
+            Err(InvalidNickname)
+        );
+    }
+
+    #[test]
+    fn test_parse_nick_command() {
+        assert_eq!(parse_nick_command("/nick alice"), Some("alice"));
+        assert_eq!(parse_nick_command("/nick  alice "), Some("alice"));
+        assert_eq!(parse_nick_command("/nick "), Some(""));
+        assert_eq!(parse_nick_command("/nickname alice"), None);
+        assert_eq!(parse_nick_command("hello /nick alice"), None);
+    }
+}
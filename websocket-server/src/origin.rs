@@ -0,0 +1,132 @@
+use crate::handshake::HttpRequest;
+
+/*
+    Cross-Site WebSocket Hijacking
+
+    브라우저는 다른 사이트의 페이지에서도 우리 서버로 웹소켓을 열어줘요. 쿠키까지 붙여서요.
+    HTTP 요청과 달리 웹소켓에는 CORS 검사가 없어서, 아무 사이트나 사용자인 척 채팅을 읽고 쓸 수 있습니다.
+    대신 브라우저는 어느 페이지에서 연결했는지 Origin 헤더에 꼭 적어주니, 서버가 그걸 보고 막아야 해요.
+
+    허락하는 것
+    - Origin이 없는 요청: 브라우저가 아니에요. (chat-client 같은 프로그램) 이런 클라이언트는 Origin을 마음대로 적을 수 있으니 막아도 의미가 없어요.
+    - Origin의 host가 Host 헤더와 같은 요청: 우리가 준 페이지에서 연결한 거예요.
+    - `allowed_origins`에 있는 Origin. `*`가 있으면 전부.
+    샌드박스된 iframe이나 file:// 페이지는 Origin에 "null"을 보내요. 이것도 허락 목록에 적지 않는 한 막습니다.
+*/
+pub(crate) fn is_allowed_origin(request: &HttpRequest, allowed_origins: &[String]) -> bool {
+    let mut origins = request.header_values("Origin");
+    let origin = match (origins.next(), origins.next()) {
+        (None, _) => return true,
+        (Some(origin), None) => origin.trim(),
+        // Origin이 여러 개면 어느 쪽을 믿어야 할지 몰라요.
+        (Some(_), Some(_)) => return false,
+    };
+
+    if allowed_origins
+        .iter()
+        .any(|allowed| allowed == "*" || same_origin(allowed, origin))
+    {
+        return true;
+    }
+
+    let host = request.header_values("Host").next().map(str::trim);
+    let origin_host = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"));
+    match (origin_host, host) {
+        (Some(origin_host), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+/// scheme과 host는 대소문자를 구분하지 않아요. 끝에 `/`를 붙여 적어도 봐줍니다.
+fn same_origin(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/')
+        .eq_ignore_ascii_case(b.trim_end_matches('/'))
+}
+
+/*
+    CORS
+
+    웹소켓이 아닌 HTTP 응답(GET /, GET /metrics)에도 같은 규칙을 적용해요.
+    다른 사이트의 스크립트가 fetch로 채팅 기록을 읽어가면 안되니까요.
+    허락된 Origin에만 Access-Control-Allow-Origin을 붙이고, 나머지는 브라우저가 알아서 막습니다.
+    응답이 Origin마다 다르니 캐시가 섞이지 않게 Vary: Origin도 붙여요.
+*/
+/// 응답에 붙일 CORS 헤더들. Origin이 없거나 허락되지 않았으면 비어있어요.
+pub(crate) fn cors_headers<'a>(
+    request: &'a HttpRequest,
+    allowed_origins: &[String],
+) -> Vec<(&'static str, &'a str)> {
+    match request.header_values("Origin").next() {
+        Some(origin) if is_allowed_origin(request, allowed_origins) => vec![
+            ("Access-Control-Allow-Origin", origin.trim()),
+            ("Vary", "Origin"),
+        ],
+        _ => vec![],
+    }
+}
+
+/// `OPTIONS` preflight에 대한 대답에 붙일 헤더들. 우리는 GET만 받아요.
+pub(crate) const PREFLIGHT_HEADERS: &[(&str, &str)] = &[
+    ("Access-Control-Allow-Methods", "GET, OPTIONS"),
+    ("Access-Control-Max-Age", "600"),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest::new("GET", "/", headers)
+    }
+
+    #[test]
+    fn test_is_allowed_origin() {
+        let allowed_origins = vec!["https://chat.example.com/".to_string()];
+        let is_allowed =
+            |headers: &[(&str, &str)]| is_allowed_origin(&request(headers), &allowed_origins);
+
+        // 브라우저가 아닌 클라이언트
+        assert!(is_allowed(&[("Host", "localhost:8080")]));
+        // 우리가 준 페이지
+        assert!(is_allowed(&[
+            ("Host", "localhost:8080"),
+            ("Origin", "http://LOCALHOST:8080")
+        ]));
+        assert!(is_allowed(&[
+            ("Host", "localhost:8080"),
+            ("Origin", "https://Chat.Example.com")
+        ]));
+
+        assert!(!is_allowed(&[
+            ("Host", "localhost:8080"),
+            ("Origin", "https://evil.example.com")
+        ]));
+        // 포트가 다르면 다른 Origin
+        assert!(!is_allowed(&[
+            ("Host", "localhost:8080"),
+            ("Origin", "http://localhost:9090")
+        ]));
+        assert!(!is_allowed(&[
+            ("Host", "localhost:8080"),
+            ("Origin", "null")
+        ]));
+        assert!(!is_allowed(&[
+            ("Host", "localhost:8080"),
+            ("Origin", "http://localhost:8080"),
+            ("Origin", "https://evil.example.com")
+        ]));
+    }
+
+    #[test]
+    fn test_wildcard_allows_every_origin() {
+        let request = request(&[("Host", "localhost"), ("Origin", "null")]);
+        assert!(is_allowed_origin(&request, &["*".to_string()]));
+        assert_eq!(
+            cors_headers(&request, &["*".to_string()]),
+            vec![("Access-Control-Allow-Origin", "null"), ("Vary", "Origin")]
+        );
+        assert_eq!(cors_headers(&request, &[]), vec![]);
+    }
+}
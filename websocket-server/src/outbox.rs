@@ -0,0 +1,473 @@
+use crate::config::SlowConsumerPolicy;
+use std::{
+    collections::{HashSet, VecDeque},
+    ops::RangeInclusive,
+    sync::Mutex,
+};
+use tokio::sync::Notify;
+use websocket_codec::{CloseCode, CloseFrame, Message};
+
+/*
+    유저 한 명에게 보낼 것들을 쌓아두는 큐.
+
+    예전에는 크기가 정해진 mpsc 채널을 썼는데, 꽉 차면 send().await가 자리가 날 때까지 기다려요.
+    그것도 UserTxs lock을 잡은 채로요. 그러면 느린 유저 한 명 때문에 모두가 메시지를 못 받아요.
+
+    그래서 넣는 쪽(push)은 절대 기다리지 않게 직접 만들었어요.
+    - 자리가 있으면 넣고 끝.
+    - 꽉 찼으면 SlowConsumerPolicy대로 처리하고 끝.
+    mpsc로는 보내는 쪽에서 가장 오래된 걸 꺼내 버릴 수가 없어서 VecDeque를 씁니다.
+    lock은 await 없이 잠깐만 잡으니 std Mutex로 충분해요.
+*/
+pub(crate) struct Outbox {
+    state: Mutex<State>,
+    /// 꺼내는 쪽(send task)은 하나뿐이라 notify_one으로 깨워요. 아무도 안 기다리면 다음 한 번을 기억해줘요.
+    notify: Notify,
+    capacity: usize,
+    policy: SlowConsumerPolicy,
+}
+
+#[derive(Default)]
+struct State {
+    /// Message, Notice, Error만 쌓여요.
+    queue: VecDeque<Outgoing>,
+    /// ReplayFromDb에서 꽉 찼거나 이어받는(resume) 중이라 메시지는 쌓지 않고 있어요. 놓친 건 DB에 있어요.
+    lagging: bool,
+    /// send task가 DB에서 읽어가야 해요.
+    replay_requested: bool,
+    /// 꽉 차서 처음 못 넣은 메시지 바로 앞의 id, 또는 클라이언트가 여기까지 받았다고 한 id. 여기 뒤부터 DB에서 읽어요.
+    replay_after: i64,
+    /// DB에서 읽기 시작한 뒤부터 다 따라잡을 때까지 이 유저가 직접 보낸 메시지들. 자기 메시지는 돌려받지 않아야 하니 DB에서 읽을 때 빼요.
+    own_ids: Option<HashSet<i64>>,
+    /// 이어받기 전에 이 연결로 이미 보낸 메시지들의 id 범위. 이것도 DB에서 읽을 때 빼요.
+    already_sent: Option<RangeInclusive<i64>>,
+    /// 따라잡는 중에 온 알림과 에러. DB에 없으니 버리면 안 되고, 바로 보내면 DB에서 읽을 더 오래된 메시지들보다 앞서요.
+    /// 그래서 다 따라잡을 때까지 들고 있다가 큐로 옮겨요. (stop_lagging)
+    held: VecDeque<Outgoing>,
+    /// DropOldest로 메시지를 버린 적이 있어요. 그러면 보낸 메시지들의 id 사이에 못 보낸 게 끼어 있을 수 있어요.
+    dropped_messages: bool,
+    /// 큐를 다 보낸 뒤에 보낼 Close. 한 번 정해지면 더 넣지 않아요.
+    close_frame: Option<CloseFrame>,
+    closing: bool,
+}
+
+/// DB에 저장된 메시지 하나. id는 DB에서 다시 읽을 때 어디까지 보냈는지 알려고 들고 다녀요.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Delivery {
+    pub(crate) id: i64,
+    /// 손님이 보낸 메시지면 None.
+    pub(crate) author: Option<String>,
+    /// 저장한 시각(유닉스 밀리초). 시각을 적기 전에 저장된 메시지면 None.
+    pub(crate) timestamp: Option<i64>,
+    pub(crate) message: Message,
+}
+
+/// send task가 다음에 할 일.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Outgoing {
+    Message(Delivery),
+    /// 누가 들어왔다 같은 알림. DB에 저장하지 않아서 id가 없어요.
+    Notice(String),
+    /// 이 유저가 보낸 게 잘못됐다는 대답. Notice처럼 DB에 없어요.
+    Error(String),
+    /// `after_id` 뒤로 놓친 메시지를 DB에서 읽어서 보내주세요.
+    Replay {
+        after_id: i64,
+    },
+    /// 이걸 보내고 닫아주세요.
+    Close(CloseFrame),
+}
+
+/// push의 결과. 뿌리는 쪽은 이걸 보고 목록에서 빼거나 숫자를 세요.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Pushed {
+    Queued,
+    /// 넣긴 했는데 가장 오래된 메시지 하나를 버렸어요.
+    DroppedOldest,
+    /// 방금 꽉 차서 DB에서 다시 읽기 시작해요.
+    StartedLagging,
+    /// 이미 DB에서 다시 읽는 중이라 넣지 않았어요.
+    Lagging,
+    /// 꽉 찼는데 DB에서 다시 읽을 수 없는 알림(이나 에러)이라 버렸어요.
+    SkippedNotice,
+    /// 꽉 차서 1008로 끊기로 했어요. 목록에서 빼주세요.
+    Disconnected,
+    /// 이미 닫는 중이에요.
+    Closed,
+}
+
+impl Outbox {
+    pub(crate) fn new(capacity: usize, policy: SlowConsumerPolicy) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            notify: Notify::new(),
+            capacity,
+            policy,
+        }
+    }
+
+    /// 절대 기다리지 않아요.
+    pub(crate) fn push(&self, delivery: Delivery) -> Pushed {
+        // 메시지는 id 순서대로 오니, 이것보다 앞의 메시지는 전부 큐에 있거나 이미 보냈어요.
+        let replay_after = delivery.id - 1;
+        self.enqueue(Outgoing::Message(delivery), Some(replay_after))
+    }
+
+    /*
+        알림도 메시지와 같은 큐에 넣어서 순서를 지켜요.
+        DB에서 따라잡는 중이면 다 따라잡을 때까지 들고 있어요. DB에 없으니 버리면 다시 받을 길이 없어요.
+        큐(따라잡는 중이면 들고 있는 것)가 꽉 찼을 때 ReplayFromDb라면 버려요.
+    */
+    pub(crate) fn push_notice(&self, notice: String) -> Pushed {
+        self.enqueue(Outgoing::Notice(notice), None)
+    }
+
+    pub(crate) fn push_error(&self, reason: String) -> Pushed {
+        self.enqueue(Outgoing::Error(reason), None)
+    }
+
+    /// `replay_after`는 꽉 찼을 때 DB의 어디부터 다시 읽으면 되는지. DB에 없는 것이면 None.
+    fn enqueue(&self, outgoing: Outgoing, replay_after: Option<i64>) -> Pushed {
+        let mut state = self.state.lock().unwrap();
+        if state.closing {
+            return Pushed::Closed;
+        }
+        if state.lagging {
+            // 메시지는 나중에 DB에서 읽어요.
+            if replay_after.is_some() {
+                return Pushed::Lagging;
+            }
+            if state.held.len() >= self.capacity {
+                return Pushed::SkippedNotice;
+            }
+            state.held.push_back(outgoing);
+            return Pushed::Queued;
+        }
+
+        let pushed = if state.queue.len() < self.capacity {
+            state.queue.push_back(outgoing);
+            Pushed::Queued
+        } else {
+            match self.policy {
+                SlowConsumerPolicy::DropOldest => {
+                    if let Some(Outgoing::Message(_)) = state.queue.pop_front() {
+                        state.dropped_messages = true;
+                    }
+                    state.queue.push_back(outgoing);
+                    Pushed::DroppedOldest
+                }
+                SlowConsumerPolicy::Disconnect => {
+                    // 어차피 못 따라오니 쌓인 걸 다 보내줄 필요 없이 바로 닫아요.
+                    state.queue.clear();
+                    state.closing = true;
+                    state.close_frame = Some(CloseFrame::new(
+                        CloseCode::POLICY_VIOLATION,
+                        "Too slow to receive messages",
+                    ));
+                    Pushed::Disconnected
+                }
+                SlowConsumerPolicy::ReplayFromDb => {
+                    let Some(replay_after) = replay_after else {
+                        return Pushed::SkippedNotice;
+                    };
+                    state.replay_after = replay_after;
+                    state.lagging = true;
+                    state.replay_requested = true;
+                    state.own_ids.get_or_insert_with(HashSet::new);
+                    Pushed::StartedLagging
+                }
+            }
+        };
+        drop(state);
+        self.notify.notify_one();
+
+        pushed
+    }
+
+    /// 쌓인 메시지를 다 보낸 뒤에 `close_frame`을 보내게 해요.
+    pub(crate) fn close(&self, close_frame: CloseFrame) {
+        let mut state = self.state.lock().unwrap();
+        if state.closing {
+            return;
+        }
+        state.closing = true;
+        state.close_frame = Some(close_frame);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// DB에서 한 번에 다 못 읽었으면 불러주세요. Ping 같은 다른 일을 하고 나서 다시 Replay가 나와요.
+    pub(crate) fn continue_replay(&self) {
+        self.state.lock().unwrap().replay_requested = true;
+        self.notify.notify_one();
+    }
+
+    /*
+        DB에서 더 읽을 게 없으면 다시 큐에 쌓기 시작해요.
+        그런데 마지막으로 읽은 뒤와 이걸 부르기 전 사이에 저장된 메시지는 DB에도 안 읽혔고 큐에도 안 들어왔어요.
+        그러니 이걸 부른 다음에 DB를 한 번 더 읽어야 해요. 그 뒤로 큐에 들어온 것과 겹치는 건 id로 걸러요.
+        따라잡는 동안 들고 있던 알림은 이때 큐에 넣어요. 새로 오는 메시지보다는 앞이에요.
+    */
+    pub(crate) fn stop_lagging(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.lagging = false;
+        let held = std::mem::take(&mut state.held);
+        state.queue.extend(held);
+    }
+
+    /// 한 번 더 읽는 것까지 끝났으면 불러주세요.
+    pub(crate) fn finish_replay(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.own_ids = None;
+        state.already_sent = None;
+    }
+
+    /*
+        클라이언트가 `after_id`까지는 받았다고 했어요. (resume)
+        꽉 찼을 때처럼 큐에 쌓는 걸 멈추고 DB에서 `after_id` 뒤를 전부 읽어 보낸 다음, 다 따라잡으면 큐로 돌아가요.
+        그 사이에 저장된 메시지는 DB에 있거나, 따라잡은 뒤 큐에 들어오니 빠지는 게 없어요.
+        큐에 있던 메시지들도 `after_id` 뒤라면 DB에서 다시 읽혀요. 그래서 버려요. 알림은 DB에 없으니 남겨요.
+        이어받는 동안 오는 알림과 에러는 다 따라잡은 뒤에 보내요. (enqueue)
+        send task가 이어받기 전에 이미 보낸 것이 있다면 `already_sent`로 알려주세요. 다시 보내지 않아요.
+        다만 DropOldest로 버린 메시지가 있으면 그 범위 안에 못 보낸 게 섞여 있을 수 있어요.
+        그때는 범위를 믿지 않고 전부 다시 보내요. 빠지는 것보다는 겹치는 게 나아요. (chat.v2라면 id로 거를 수 있어요)
+    */
+    pub(crate) fn resume(&self, after_id: i64, already_sent: Option<RangeInclusive<i64>>) {
+        let mut state = self.state.lock().unwrap();
+        if state.closing {
+            return;
+        }
+        state
+            .queue
+            .retain(|outgoing| !matches!(outgoing, Outgoing::Message(_)));
+        state.lagging = true;
+        state.replay_requested = true;
+        state.replay_after = after_id;
+        state.own_ids.get_or_insert_with(HashSet::new);
+        state.already_sent = already_sent.filter(|_| !state.dropped_messages);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// 이 유저가 직접 보낸 메시지가 저장됐어요. 다른 유저들에게 push하는 것과 같은 lock 안에서 불러주세요.
+    pub(crate) fn note_own_message(&self, id: i64) {
+        if let Some(own_ids) = &mut self.state.lock().unwrap().own_ids {
+            own_ids.insert(id);
+        }
+    }
+
+    /// DB에서 다시 읽은 메시지 중 이 유저가 보냈거나 이미 받은 것이라 보내지 않아야 하는지.
+    pub(crate) fn skips_replay(&self, id: i64) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .own_ids
+            .as_ref()
+            .is_some_and(|own_ids| own_ids.contains(&id))
+            || state
+                .already_sent
+                .as_ref()
+                .is_some_and(|already_sent| already_sent.contains(&id))
+    }
+
+    /// 보낼 게 생길 때까지 기다려요. select!에서 취소돼도 잃어버리는 건 없어요.
+    pub(crate) async fn recv(&self) -> Outgoing {
+        loop {
+            if let Some(outgoing) = self.try_recv() {
+                return outgoing;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// 지금 보낼 게 없으면 None.
+    pub(crate) fn try_recv(&self) -> Option<Outgoing> {
+        let mut state = self.state.lock().unwrap();
+        // 큐에 있는 메시지는 DB에서 다시 읽을 것들보다 오래된 거라 먼저 보내요. 알림도 기다리게 하지 않아요.
+        if let Some(outgoing) = state.queue.pop_front() {
+            return Some(outgoing);
+        }
+        if std::mem::take(&mut state.replay_requested) && !state.closing {
+            return Some(Outgoing::Replay {
+                after_id: state.replay_after,
+            });
+        }
+        state.close_frame.take().map(Outgoing::Close)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn delivery(id: i64) -> Delivery {
+        Delivery {
+            id,
+            author: None,
+            timestamp: None,
+            message: Message::Text(id.to_string()),
+        }
+    }
+
+    fn drain(outbox: &Outbox) -> Vec<Outgoing> {
+        std::iter::from_fn(|| outbox.try_recv()).collect()
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_newest_messages() {
+        let outbox = Outbox::new(2, SlowConsumerPolicy::DropOldest);
+        assert_eq!(outbox.push(delivery(1)), Pushed::Queued);
+        assert_eq!(outbox.push(delivery(2)), Pushed::Queued);
+        assert_eq!(outbox.push(delivery(3)), Pushed::DroppedOldest);
+
+        assert_eq!(
+            drain(&outbox),
+            vec![
+                Outgoing::Message(delivery(2)),
+                Outgoing::Message(delivery(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disconnect_skips_queued_messages() {
+        let outbox = Outbox::new(1, SlowConsumerPolicy::Disconnect);
+        assert_eq!(outbox.push(delivery(1)), Pushed::Queued);
+        assert_eq!(outbox.push(delivery(2)), Pushed::Disconnected);
+        assert_eq!(outbox.push(delivery(3)), Pushed::Closed);
+
+        let Some(Outgoing::Close(close_frame)) = outbox.try_recv() else {
+            panic!("expected Close");
+        };
+        assert_eq!(close_frame.code, Some(CloseCode::POLICY_VIOLATION));
+        assert_eq!(outbox.try_recv(), None);
+    }
+
+    #[test]
+    fn test_replay_comes_after_queued_messages() {
+        let outbox = Outbox::new(1, SlowConsumerPolicy::ReplayFromDb);
+        assert_eq!(outbox.push(delivery(1)), Pushed::Queued);
+        assert_eq!(outbox.push(delivery(2)), Pushed::StartedLagging);
+        assert_eq!(outbox.push(delivery(3)), Pushed::Lagging);
+        outbox.note_own_message(4);
+        assert_eq!(
+            drain(&outbox),
+            vec![
+                Outgoing::Message(delivery(1)),
+                Outgoing::Replay { after_id: 1 }
+            ]
+        );
+        assert!(outbox.skips_replay(4));
+
+        outbox.stop_lagging();
+        outbox.finish_replay();
+        assert!(!outbox.skips_replay(4));
+        assert_eq!(outbox.push(delivery(5)), Pushed::Queued);
+        assert_eq!(drain(&outbox), vec![Outgoing::Message(delivery(5))]);
+    }
+
+    #[test]
+    fn test_resume_replaces_queued_messages_with_replay() {
+        let outbox = Outbox::new(4, SlowConsumerPolicy::Disconnect);
+        outbox.push(delivery(5));
+        outbox.push_notice("* alice joined".to_string());
+        outbox.resume(2, Some(4..=5));
+        // 다 따라잡기 전에는 꽉 차지 않아요. 정책과 상관없이 DB에서 읽어요.
+        for id in 6..16 {
+            assert_eq!(outbox.push(delivery(id)), Pushed::Lagging);
+        }
+
+        assert_eq!(
+            drain(&outbox),
+            vec![
+                Outgoing::Notice("* alice joined".to_string()),
+                Outgoing::Replay { after_id: 2 }
+            ]
+        );
+        assert!(outbox.skips_replay(5));
+        assert!(!outbox.skips_replay(3));
+        outbox.stop_lagging();
+        outbox.finish_replay();
+        assert!(!outbox.skips_replay(5));
+        assert_eq!(outbox.push(delivery(16)), Pushed::Queued);
+    }
+
+    #[test]
+    fn test_notices_wait_until_caught_up() {
+        let outbox = Outbox::new(2, SlowConsumerPolicy::Disconnect);
+        outbox.resume(0, None);
+        assert_eq!(outbox.push(delivery(1)), Pushed::Lagging);
+        assert_eq!(
+            outbox.push_error("Invalid envelope".to_string()),
+            Pushed::Queued
+        );
+        assert_eq!(
+            outbox.push_notice("* alice joined".to_string()),
+            Pushed::Queued
+        );
+        assert_eq!(
+            outbox.push_notice("* alice left".to_string()),
+            Pushed::SkippedNotice
+        );
+        assert_eq!(drain(&outbox), vec![Outgoing::Replay { after_id: 0 }]);
+
+        outbox.stop_lagging();
+        assert_eq!(
+            drain(&outbox),
+            vec![
+                Outgoing::Error("Invalid envelope".to_string()),
+                Outgoing::Notice("* alice joined".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resume_after_dropping_messages_replays_everything() {
+        let outbox = Outbox::new(1, SlowConsumerPolicy::DropOldest);
+        assert_eq!(outbox.push(delivery(1)), Pushed::Queued);
+        // send task가 1을 보내기 전에 2가 와서 1을 버려요. 그 다음 2를 보냈다면 보낸 범위는 1..=2지만 1은 못 받았어요.
+        assert_eq!(outbox.push(delivery(2)), Pushed::DroppedOldest);
+        outbox.resume(0, Some(1..=2));
+        assert!(!outbox.skips_replay(1));
+        assert!(!outbox.skips_replay(2));
+    }
+
+    #[test]
+    fn test_notices_keep_order_but_are_not_replayed() {
+        let outbox = Outbox::new(2, SlowConsumerPolicy::ReplayFromDb);
+        assert_eq!(outbox.push(delivery(1)), Pushed::Queued);
+        assert_eq!(
+            outbox.push_notice("* alice joined".to_string()),
+            Pushed::Queued
+        );
+        assert_eq!(
+            outbox.push_notice("* alice left".to_string()),
+            Pushed::SkippedNotice
+        );
+        assert_eq!(outbox.push(delivery(2)), Pushed::StartedLagging);
+
+        assert_eq!(
+            drain(&outbox),
+            vec![
+                Outgoing::Message(delivery(1)),
+                Outgoing::Notice("* alice joined".to_string()),
+                Outgoing::Replay { after_id: 1 }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_close_is_sent_after_queued_messages() {
+        let outbox = Outbox::new(4, SlowConsumerPolicy::Disconnect);
+        outbox.push(delivery(1));
+        outbox.close(CloseFrame::new(CloseCode::GOING_AWAY, ""));
+        assert_eq!(outbox.push(delivery(2)), Pushed::Closed);
+
+        assert_eq!(
+            drain(&outbox),
+            vec![
+                Outgoing::Message(delivery(1)),
+                Outgoing::Close(CloseFrame::new(CloseCode::GOING_AWAY, ""))
+            ]
+        );
+    }
+}
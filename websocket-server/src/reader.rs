@@ -0,0 +1,139 @@
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt},
+    time::Instant,
+};
+use websocket_codec::{DecodeError, Decoder, Frame};
+
+/*
+    예전에는 read_exact로 헤더, 길이, 마스킹 키, payload를 차례로 읽었어요.
+    그런데 read_exact는 취소에 안전하지 않아요.
+    timeout이나 select!가 read_exact를 중간에 끊으면, 그때까지 읽은 바이트는 read_exact의 버퍼와 함께 사라지고
+    다음 읽기는 프레임 한가운데부터 시작하게 됩니다. 스트림이 망가지는 거죠.
+
+    그래서 읽은 바이트는 그 자리에서 전부 Decoder에 넘겨요. (fill_buf + consume)
+    fill_buf는 아무것도 안 읽었거나, 읽은 걸 BufReader 안에 남겨두거나 둘 중 하나라서
+    어느 await에서 끊겨도 잃어버리는 바이트가 없습니다.
+*/
+pub(crate) struct FrameReader<R> {
+    reader: R,
+    decoder: Decoder,
+    /// 메시지의 첫 바이트가 오고 나서 마지막 조각까지 오는 데 걸려도 되는 시간.
+    message_timeout: Duration,
+    /// 받는 중인 메시지가 있을 때만 Some. 가만히 있는 연결은 Ping/Pong이 챙겨요.
+    message_deadline: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ReadFrameError {
+    Io(std::io::Error),
+    Decode(DecodeError),
+    /// 메시지를 보내기 시작해놓고 제시간에 끝내지 않았어요.
+    Timeout,
+}
+
+impl<R: AsyncBufRead + Unpin> FrameReader<R> {
+    pub(crate) fn new(reader: R, decoder: Decoder, message_timeout: Duration) -> Self {
+        Self {
+            reader,
+            decoder,
+            message_timeout,
+            message_deadline: None,
+        }
+    }
+
+    /// 프레임 하나가 다 올 때까지 읽습니다. 중간에 취소돼도 다음에 이어서 읽을 수 있어요.
+    pub(crate) async fn read_frame(&mut self) -> Result<Frame, ReadFrameError> {
+        loop {
+            if let Some(frame) = self.decoder.decode().map_err(ReadFrameError::Decode)? {
+                return Ok(frame);
+            }
+
+            let buf = match self.message_deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, self.reader.fill_buf())
+                    .await
+                    .map_err(|_| ReadFrameError::Timeout)?,
+                None => self.reader.fill_buf().await,
+            }
+            .map_err(ReadFrameError::Io)?;
+            if buf.is_empty() {
+                return Err(ReadFrameError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+
+            self.decoder.feed(buf);
+            let length = buf.len();
+            self.reader.consume(length);
+
+            // 메시지의 첫 바이트가 왔을 때부터 시간을 잽니다.
+            if self.message_deadline.is_none() {
+                self.message_deadline = Some(Instant::now() + self.message_timeout);
+            }
+        }
+    }
+
+    /// 메시지 하나를 다 받았으면 불러주세요. 다음 메시지는 시간을 새로 잽니다.
+    pub(crate) fn finish_message(&mut self) {
+        self.message_deadline =
+            (self.decoder.buffered_len() > 0).then(|| Instant::now() + self.message_timeout);
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncWriteExt, BufReader};
+    use websocket_codec::{Opcode, Role};
+
+    const MASK: [u8; 4] = [1, 2, 3, 4];
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_frame_survives_cancellation() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(
+            BufReader::new(server),
+            Decoder::new(Role::Server),
+            Duration::from_secs(60),
+        );
+
+        let bytes = Frame::new(Opcode::Text, b"hello".to_vec()).encode(Some(MASK));
+        client.write_all(&bytes[..4]).await.unwrap();
+
+        // 반만 온 상태에서 읽기를 여러번 끊어봐요.
+        for _ in 0..3 {
+            let result = tokio::time::timeout(Duration::from_millis(10), reader.read_frame()).await;
+            assert!(result.is_err());
+        }
+
+        client.write_all(&bytes[4..]).await.unwrap();
+        assert_eq!(
+            reader.read_frame().await.unwrap(),
+            Frame::new(Opcode::Text, b"hello".to_vec())
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_frame_enforces_message_deadline() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut reader = FrameReader::new(
+            BufReader::new(server),
+            Decoder::new(Role::Server),
+            Duration::from_secs(5),
+        );
+
+        // 아무것도 안 보내고 있는 건 괜찮아요.
+        let idle = tokio::time::timeout(Duration::from_secs(60), reader.read_frame()).await;
+        assert!(idle.is_err());
+
+        // 보내기 시작했으면 5초 안에 끝내야 해요.
+        let bytes = Frame::new(Opcode::Text, b"hello".to_vec()).encode(Some(MASK));
+        client.write_all(&bytes[..4]).await.unwrap();
+        assert!(matches!(
+            reader.read_frame().await,
+            Err(ReadFrameError::Timeout)
+        ));
+    }
+}
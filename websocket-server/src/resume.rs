@@ -0,0 +1,69 @@
+use crate::handshake::query_value;
+
+/*
+    이어받기(resume)
+
+    페이지를 받은 뒤 WebSocket을 연결하기 전에, 또는 연결이 끊겼다 다시 붙는 사이에 저장된 메시지는 못 받아요.
+    그래서 클라이언트가 마지막으로 받은 메시지의 id를 알려주면, 그 뒤의 메시지를 DB에서 읽어 먼저 보내주고
+    다 따라잡으면 평소처럼 바로바로 보내요. (Outbox::resume)
+
+    알려주는 방법은 둘이에요.
+    - 업그레이드할 때 `?since=42`
+    - 연결한 뒤 첫 메시지로 `/resume 42` (chat.v2라면 `{"type":"resume","since":42}`)
+    업그레이드할 때 알려주는 게 더 좋아요. 101을 보내기 전부터 DB에서 읽기로 해두니 순서까지 딱 맞아요.
+    첫 메시지로 알려주면 그게 도착하기 전에 이미 보낸 메시지가 있을 수 있어요. 그건 다시 보내지 않아서,
+    빠지거나 겹치는 건 없지만 그 몇 개만 더 오래된 메시지들보다 먼저 도착할 수 있어요.
+*/
+
+const RESUME_COMMAND: &str = "/resume ";
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct InvalidSince;
+
+/// 업그레이드 요청 경로의 `since=` 값. 없으면 None이고, 0 이상의 정수가 아니면 에러예요.
+pub(crate) fn since_from_path(path: &str) -> Result<Option<i64>, InvalidSince> {
+    query_value(path, "since")
+        .map(|since| parse_since(since).ok_or(InvalidSince))
+        .transpose()
+}
+
+/// `/resume 42`면 `Some(Ok(42))`, `/resume`으로 시작하는데 id가 이상하면 `Some(Err(..))`.
+pub(crate) fn parse_resume_command(text: &str) -> Option<Result<i64, InvalidSince>> {
+    let since = text.strip_prefix(RESUME_COMMAND)?;
+    Some(parse_since(since.trim()).ok_or(InvalidSince))
+}
+
+fn parse_since(since: &str) -> Option<i64> {
+    since.parse().ok().filter(|since| *since >= 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_since_from_path() {
+        assert_eq!(since_from_path("/"), Ok(None));
+        assert_eq!(since_from_path("/?nick=alice"), Ok(None));
+        assert_eq!(since_from_path("/?since=0"), Ok(Some(0)));
+        assert_eq!(since_from_path("/rooms/rust?nick=a&since=42"), Ok(Some(42)));
+
+        for path in [
+            "/?since=",
+            "/?since=-1",
+            "/?since=abc",
+            "/?since=99999999999999999999",
+        ] {
+            assert_eq!(since_from_path(path), Err(InvalidSince), "{path}");
+        }
+    }
+
+    #[test]
+    fn test_parse_resume_command() {
+        assert_eq!(parse_resume_command("/resume 42"), Some(Ok(42)));
+        assert_eq!(parse_resume_command("/resume  7 "), Some(Ok(7)));
+        assert_eq!(parse_resume_command("/resume x"), Some(Err(InvalidSince)));
+        assert_eq!(parse_resume_command("/resumed 42"), None);
+        assert_eq!(parse_resume_command("hello"), None);
+    }
+}
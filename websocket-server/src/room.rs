@@ -0,0 +1,438 @@
+use crate::{
+    hub::Hub,
+    outbox::{Delivery, Outbox},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+use websocket_codec::CloseFrame;
+
+/*
+    채팅방
+
+    `/rooms/{name}`으로 연결하면 그 방에 들어가요. `/`는 기본 방(lobby)이에요.
+    방마다 Hub가 따로 있어서, 메시지는 같은 방 사람들에게만 뿌려지고 DB에도 방 이름과 함께 저장돼요.
+    방은 처음 누가 들어올 때 생기고, 마지막 사람이 나가면 없어져요. (메시지 기록은 DB에 남아요)
+
+    방 목록(Rooms)의 lock은 들어오고 나갈 때(닉네임을 바꿀 때도) HashMap을 몇 번 찾는 동안만 잡아요.
+    메시지를 뿌릴 때는 각자 들고 있는 자기 방의 Hub만 쓰니 이 lock을 잡지 않아요.
+    들어오고 나갈 때의 알림도 방 사람 수만큼 걸리니, 목록만 고치고 lock을 놓은 다음에 뿌려요.
+
+    닉네임이 있는 유저가 들어오고, 나가고, 이름을 바꾸면 같은 방 사람들에게 알림(`* alice joined` 같은)을 보내요.
+    한 방 안에서 같은 닉네임은 한 명만 쓸 수 있어요.
+*/
+
+/// 방이 생기기 전의 메시지도 여기 있어요. (db.rs의 마이그레이션과 같은 이름이어야 해요)
+pub(crate) const DEFAULT_ROOM: &str = "lobby";
+
+const MAX_ROOM_NAME_LENGTH: usize = 64;
+
+/*
+    Hub는 뿌릴 때마다 모든 조각(shard)을 하나씩 잠가요.
+    기본 방에는 `/`로 연결하는 예전 클라이언트가 모두 모이니 코어 수만큼 나눈 Hub를 그대로 쓰고,
+    `/rooms/{name}`은 보통 몇 명 안 되니 조금만 나눠요. 두 명 있는 방에서 조각 수십 개를 잠글 필요는 없어요.
+*/
+const ROOM_SHARD_COUNT: usize = 4;
+
+fn new_hub(room: &str) -> Hub {
+    if room == DEFAULT_ROOM {
+        Hub::new()
+    } else {
+        Hub::with_shard_count(ROOM_SHARD_COUNT)
+    }
+}
+
+/// `/`는 기본 방, `/rooms/{name}`은 그 방. 쿼리(`?...`)는 빼고 봐요. 그 외의 경로나 쓸 수 없는 이름이면 None.
+pub(crate) fn room_from_path(path: &str) -> Option<&str> {
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    if path == "/" {
+        return Some(DEFAULT_ROOM);
+    }
+
+    // 이름은 URL, HTML, JSON 어디에 넣어도 이스케이프할 필요 없는 글자로만 만들 수 있어요.
+    let name = path.strip_prefix("/rooms/")?;
+    let valid = (1..=MAX_ROOM_NAME_LENGTH).contains(&name.len())
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_');
+    valid.then_some(name)
+}
+
+#[derive(Default)]
+pub(crate) struct Rooms {
+    inner: Mutex<RoomsInner>,
+}
+
+#[derive(Default)]
+struct RoomsInner {
+    rooms: HashMap<String, Room>,
+    /// 서버가 꺼지는 중이면, 이제 막 들어오는 사람도 바로 이걸로 닫아요.
+    closing: Option<CloseFrame>,
+}
+
+struct Room {
+    hub: Arc<Hub>,
+    /// id -> 닉네임(손님이면 None). Hub에서는 느린 유저가 먼저 빠질 수 있으니, 누가 있는지는 따로 적어둬요.
+    members: BTreeMap<u64, Option<String>>,
+    /// 닉네임 -> id. 이미 쓰는 이름인지 members를 훑지 않고 바로 알아요.
+    nicknames: HashMap<String, u64>,
+    /// Membership::lock_message_order
+    message_order: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// 같은 방에 이미 그 닉네임을 쓰는 사람이 있어요.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct NicknameTaken;
+
+/// `GET /rooms`의 대답 한 줄.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct RoomSummary {
+    pub(crate) name: String,
+    pub(crate) users: usize,
+}
+
+/// `GET /rooms/{name}/users`의 대답 한 줄.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct UserSummary {
+    pub(crate) id: u64,
+    pub(crate) nickname: Option<String>,
+}
+
+impl Rooms {
+    /// 방이 없으면 만들어요. 돌려받은 Membership을 drop하면 나가요.
+    pub(crate) fn join(
+        self: &Arc<Self>,
+        room: &str,
+        id: u64,
+        nickname: Option<&str>,
+        outbox: Arc<Outbox>,
+    ) -> Result<Membership, NicknameTaken> {
+        let (hub, message_order) = {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(close_frame) = &inner.closing {
+                outbox.close(close_frame.clone());
+            }
+            let entry = inner.rooms.entry(room.to_string()).or_insert_with(|| Room {
+                hub: Arc::new(new_hub(room)),
+                members: BTreeMap::new(),
+                nicknames: HashMap::new(),
+                message_order: Arc::default(),
+            });
+            if let Some(nickname) = nickname {
+                if entry.nicknames.contains_key(nickname) {
+                    // 방금 만든 방이면 아무도 없으니 여기 올 수 없어요. 그러니 빈 방이 남지 않아요.
+                    return Err(NicknameTaken);
+                }
+                entry.nicknames.insert(nickname.to_string(), id);
+            }
+            entry.members.insert(id, nickname.map(str::to_string));
+            entry.hub.join(id, outbox.clone());
+            (entry.hub.clone(), entry.message_order.clone())
+        };
+        if let Some(nickname) = nickname {
+            hub.broadcast_notice(&format!("* {nickname} joined"), id);
+        }
+
+        Ok(Membership {
+            id,
+            room: room.to_string(),
+            nickname: Mutex::new(nickname.map(str::to_string)),
+            outbox,
+            hub,
+            message_order,
+            rooms: self.clone(),
+        })
+    }
+
+    fn leave(&self, room: &str, id: u64) {
+        let (hub, nickname) = {
+            let mut inner = self.inner.lock().unwrap();
+            let Some(entry) = inner.rooms.get_mut(room) else {
+                return;
+            };
+            entry.hub.leave(id);
+            let nickname = entry.members.remove(&id).flatten();
+            if let Some(nickname) = &nickname {
+                entry.nicknames.remove(nickname);
+            }
+            let hub = entry.hub.clone();
+            if entry.members.is_empty() {
+                inner.rooms.remove(room);
+            }
+            (hub, nickname)
+        };
+        if let Some(nickname) = nickname {
+            hub.broadcast_notice(&format!("* {nickname} left"), id);
+        }
+    }
+
+    /// 바꾸기 전 닉네임을 돌려줘요.
+    fn rename(&self, room: &str, id: u64, nickname: &str) -> Result<Option<String>, NicknameTaken> {
+        let (hub, previous) = {
+            let mut inner = self.inner.lock().unwrap();
+            let Some(entry) = inner.rooms.get_mut(room) else {
+                return Ok(None);
+            };
+            if entry.nicknames.contains_key(nickname) {
+                return Err(NicknameTaken);
+            }
+            entry.nicknames.insert(nickname.to_string(), id);
+            let previous = entry
+                .members
+                .insert(id, Some(nickname.to_string()))
+                .flatten();
+            if let Some(previous) = &previous {
+                entry.nicknames.remove(previous);
+            }
+            (entry.hub.clone(), previous)
+        };
+        // 손님이었으면 이제 막 들어온 것처럼 알려요.
+        let notice = match &previous {
+            Some(previous) => format!("* {previous} is now {nickname}"),
+            None => format!("* {nickname} joined"),
+        };
+        hub.broadcast_notice(&notice, id);
+        Ok(previous)
+    }
+
+    /// 사람이 있는 방들. 이름 순서예요.
+    pub(crate) fn list(&self) -> Vec<RoomSummary> {
+        let mut rooms: Vec<_> = self
+            .inner
+            .lock()
+            .unwrap()
+            .rooms
+            .iter()
+            .map(|(name, room)| RoomSummary {
+                name: name.clone(),
+                users: room.members.len(),
+            })
+            .collect();
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+        rooms
+    }
+
+    /// `room`에 있는 사람들. id 순서(들어온 순서)예요. 방이 없으면 비어있어요.
+    pub(crate) fn users(&self, room: &str) -> Vec<UserSummary> {
+        let inner = self.inner.lock().unwrap();
+        let Some(room) = inner.rooms.get(room) else {
+            return vec![];
+        };
+        room.members
+            .iter()
+            .map(|(id, nickname)| UserSummary {
+                id: *id,
+                nickname: nickname.clone(),
+            })
+            .collect()
+    }
+
+    /// 모든 방의 모두에게 Hub::close_all을 해요. 닫은 유저 수를 돌려줘요.
+    pub(crate) fn close_all(&self, close_frame: CloseFrame) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let closed = inner
+            .rooms
+            .values()
+            .map(|room| room.hub.close_all(close_frame.clone()))
+            .sum();
+        inner.closing = Some(close_frame);
+        closed
+    }
+}
+
+/// 방 하나에 들어가 있는 유저 한 명. recv task와 send task가 같이 들고 있다가, 둘 다 끝나면 나가요.
+pub(crate) struct Membership {
+    pub(crate) id: u64,
+    pub(crate) room: String,
+    /// recv task만 바꾸지만, 메시지를 저장할 때 같이 읽어요.
+    nickname: Mutex<Option<String>>,
+    /// 같은 방 사람들이 보낸 메시지가 여기 쌓여요.
+    pub(crate) outbox: Arc<Outbox>,
+    hub: Arc<Hub>,
+    message_order: Arc<tokio::sync::Mutex<()>>,
+    rooms: Arc<Rooms>,
+}
+
+impl Membership {
+    /// 같은 방의 나를 뺀 모두에게 보내요.
+    pub(crate) fn broadcast(&self, delivery: &Delivery) {
+        self.hub.broadcast(delivery, self.id);
+    }
+
+    /*
+        id는 저장할 때 정해지는데, 같은 방의 두 유저가 동시에 보내면 id 순서와 뿌려지는 순서가 뒤바뀔 수 있어요.
+        (A가 5번으로 저장, B가 6번으로 저장, B가 먼저 뿌림)
+        DB에서 다시 읽어 보내는 쪽은 "이 방에서 여기까지 보냈다"를 id 하나로 기억하니까, 순서가 같아야 빠지거나 겹치지 않아요.
+        그래서 저장하고 뿌리는 동안 이 lock을 잡아요.
+        다시 읽는 것도 방 하나씩이라 lock도 방마다 따로 있어요. 다른 방의 메시지는 기다리지 않아요.
+    */
+    /// 들고 있는 동안에는 같은 방에 다른 메시지가 저장되고 뿌려지지 않아요.
+    pub(crate) async fn lock_message_order(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.message_order.lock().await
+    }
+
+    /// 손님이면 None.
+    pub(crate) fn nickname(&self) -> Option<String> {
+        self.nickname.lock().unwrap().clone()
+    }
+
+    /// 같은 방 사람들에게도 알려요. `nickname`이 쓸 수 있는 이름인지는 먼저 확인해주세요.
+    pub(crate) fn rename(&self, nickname: &str) -> Result<(), NicknameTaken> {
+        // 이미 그 이름이면 자기 자신과 겹치는 거라 아무것도 안 해요.
+        if self.nickname().as_deref() == Some(nickname) {
+            return Ok(());
+        }
+        self.rooms.rename(&self.room, self.id, nickname)?;
+        *self.nickname.lock().unwrap() = Some(nickname.to_string());
+        Ok(())
+    }
+}
+
+// RAII: 어디서 어떻게 끝나든(에러로 일찍 돌아가도) 꼭 한 번 나가요.
+impl Drop for Membership {
+    fn drop(&mut self) {
+        self.rooms.leave(&self.room, self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::SlowConsumerPolicy, outbox::Outgoing};
+    use std::time::Duration;
+    use websocket_codec::Message;
+
+    #[test]
+    fn test_room_from_path() {
+        assert_eq!(room_from_path("/"), Some(DEFAULT_ROOM));
+        assert_eq!(room_from_path("/?since=3"), Some(DEFAULT_ROOM));
+        assert_eq!(room_from_path("/rooms/rust-kr_2"), Some("rust-kr_2"));
+        assert_eq!(room_from_path("/rooms/rust?since=3"), Some("rust"));
+
+        for path in [
+            "/rooms",
+            "/rooms/",
+            "/rooms/a/b",
+            "/rooms/%20",
+            "/rooms/<script>",
+            "/metrics",
+            "/other/rust",
+        ] {
+            assert_eq!(room_from_path(path), None, "{path}");
+        }
+        assert_eq!(
+            room_from_path(&format!("/rooms/{}", "a".repeat(MAX_ROOM_NAME_LENGTH + 1))),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rooms_are_created_and_removed_with_members() {
+        let rooms = Arc::new(Rooms::default());
+        let outbox = || Arc::new(Outbox::new(4, SlowConsumerPolicy::Disconnect));
+
+        let alice = rooms.join("rust", 1, None, outbox()).unwrap();
+        let bob = rooms.join("rust", 2, None, outbox()).unwrap();
+        let carol = rooms.join(DEFAULT_ROOM, 3, None, outbox()).unwrap();
+        assert_eq!(
+            rooms.list(),
+            vec![
+                RoomSummary {
+                    name: "lobby".to_string(),
+                    users: 1
+                },
+                RoomSummary {
+                    name: "rust".to_string(),
+                    users: 2
+                },
+            ]
+        );
+
+        // 다른 방에는 가지 않아요.
+        alice.broadcast(&Delivery {
+            id: 1,
+            author: None,
+            timestamp: None,
+            message: Message::Text("hi".to_string()),
+        });
+        assert!(matches!(bob.outbox.recv().await, Outgoing::Message(_)));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), carol.outbox.recv())
+                .await
+                .is_err()
+        );
+
+        drop(alice);
+        drop(carol);
+        assert_eq!(rooms.list().len(), 1);
+        drop(bob);
+        assert_eq!(rooms.list(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_message_order_is_locked_per_room() {
+        let rooms = Arc::new(Rooms::default());
+        let outbox = || Arc::new(Outbox::new(4, SlowConsumerPolicy::Disconnect));
+
+        let alice = rooms.join("rust", 1, None, outbox()).unwrap();
+        let bob = rooms.join("rust", 2, None, outbox()).unwrap();
+        let carol = rooms.join("other", 3, None, outbox()).unwrap();
+
+        let _order = alice.lock_message_order().await;
+        // 다른 방은 기다리지 않고, 같은 방은 기다려요.
+        drop(carol.lock_message_order().await);
+        assert!(bob.message_order.try_lock().is_err());
+    }
+
+    fn next_notice(outbox: &Outbox) -> String {
+        match outbox.try_recv() {
+            Some(Outgoing::Notice(notice)) => notice,
+            other => panic!("expected a notice, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_named_users_are_announced() {
+        let rooms = Arc::new(Rooms::default());
+        let outbox = || Arc::new(Outbox::new(4, SlowConsumerPolicy::Disconnect));
+
+        let guest = rooms.join("rust", 1, None, outbox()).unwrap();
+        let alice = rooms.join("rust", 2, Some("alice"), outbox()).unwrap();
+        assert_eq!(next_notice(&guest.outbox), "* alice joined");
+        assert_eq!(
+            rooms.join("rust", 3, Some("alice"), outbox()).err(),
+            Some(NicknameTaken)
+        );
+        // 다른 방에서는 같은 이름을 써도 돼요.
+        let other_alice = rooms.join("other", 4, Some("alice"), outbox()).unwrap();
+
+        guest.rename("bob").unwrap();
+        assert_eq!(next_notice(&alice.outbox), "* bob joined");
+        assert_eq!(guest.rename("alice"), Err(NicknameTaken));
+        alice.rename("carol").unwrap();
+        assert_eq!(next_notice(&guest.outbox), "* alice is now carol");
+        assert_eq!(
+            rooms.users("rust"),
+            vec![
+                UserSummary {
+                    id: 1,
+                    nickname: Some("bob".to_string())
+                },
+                UserSummary {
+                    id: 2,
+                    nickname: Some("carol".to_string())
+                },
+            ]
+        );
+
+        drop(alice);
+        assert_eq!(next_notice(&guest.outbox), "* carol left");
+        assert_eq!(other_alice.outbox.try_recv(), None);
+        // 바꾸기 전 이름과 나간 사람의 이름은 다시 쓸 수 있어요.
+        let _alice = rooms.join("rust", 5, Some("alice"), outbox()).unwrap();
+        let _carol = rooms.join("rust", 6, Some("carol"), outbox()).unwrap();
+        assert_eq!(rooms.users("nowhere"), vec![]);
+    }
+}
@@ -0,0 +1,60 @@
+/// 이 서버가 할 줄 아는 애플리케이션 프로토콜들. (`Sec-WebSocket-Protocol`)
+///
+/// 클라이언트가 아무것도 요청하지 않으면 None으로 연결되고, 예전처럼 Text와 Binary를 다 받아요.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Subprotocol {
+    /// Text 메시지만 주고받는 채팅.
+    ChatV1,
+    /// ChatV1처럼 Text만 주고받는데, 하나하나가 JSON 봉투예요. 모양은 envelope.rs에 있어요.
+    ChatV2,
+}
+
+impl Subprotocol {
+    /// 서버가 지원하는 것들. 클라이언트가 여러개를 요청하면 여기서 앞에 있는 걸 고릅니다.
+    const REGISTRY: &'static [Subprotocol] = &[Subprotocol::ChatV2, Subprotocol::ChatV1];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Subprotocol::ChatV1 => "chat.v1",
+            Subprotocol::ChatV2 => "chat.v2",
+        }
+    }
+
+    /// `Sec-WebSocket-Protocol` 헤더 값들(`chat.v2, chat.v1` 같은 목록)에서 하나를 고릅니다.
+    pub(crate) fn negotiate<'a>(header_values: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let requested = header_values
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+
+        Self::REGISTRY
+            .iter()
+            .copied()
+            .find(|subprotocol| requested.contains(&subprotocol.name()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(
+            Subprotocol::negotiate(["chat.v9, chat.v1"].into_iter()),
+            Some(Subprotocol::ChatV1)
+        );
+        assert_eq!(
+            Subprotocol::negotiate(["chat.v9", "chat.v1"].into_iter()),
+            Some(Subprotocol::ChatV1)
+        );
+        // 둘 다 된다면 새 버전을 골라요.
+        assert_eq!(
+            Subprotocol::negotiate(["chat.v1, chat.v2"].into_iter()),
+            Some(Subprotocol::ChatV2)
+        );
+        // 토큰은 대소문자를 구분해요.
+        assert_eq!(Subprotocol::negotiate(["CHAT.V1"].into_iter()), None);
+        assert_eq!(Subprotocol::negotiate(std::iter::empty()), None);
+    }
+}
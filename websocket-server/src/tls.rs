@@ -0,0 +1,47 @@
+use crate::config::TlsConfig;
+use anyhow::{anyhow, Context, Result};
+use std::{fs::File, io::BufReader, sync::Arc};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{rustls::ServerConfig, TlsAcceptor};
+
+/*
+    TLS를 켜도 그 위에서 하는 일은 똑같아요. HTTP 요청을 읽고, 핸드셰이크하고, 프레임을 주고받고.
+    달라지는 건 바이트가 TcpStream에서 바로 오는지, TlsStream을 거쳐서 오는지 뿐이에요.
+    그래서 둘 다 Stream으로 감싸서 똑같이 다룹니다.
+*/
+pub(crate) trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+pub(crate) type BoxedStream = Box<dyn Stream>;
+
+/// 인증서와 키를 읽어서 TLS 연결을 받을 준비를 합니다. 서버를 켤 때 한번만 불러요.
+pub(crate) fn load_tls_acceptor(tls: &TlsConfig) -> Result<TlsAcceptor> {
+    let certificate_chain = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(&tls.certificate_path)
+            .with_context(|| format!("Fail to open {}", tls.certificate_path.display()))?,
+    ))
+    .collect::<Result<Vec<_>, _>>()
+    .with_context(|| format!("Invalid certificate {}", tls.certificate_path.display()))?;
+    if certificate_chain.is_empty() {
+        return Err(anyhow!(
+            "No certificate in {}",
+            tls.certificate_path.display()
+        ));
+    }
+
+    // PKCS#8, PKCS#1(RSA), SEC1(EC) 중 처음 나오는 키를 씁니다.
+    let private_key = rustls_pemfile::private_key(&mut BufReader::new(
+        File::open(&tls.private_key_path)
+            .with_context(|| format!("Fail to open {}", tls.private_key_path.display()))?,
+    ))
+    .with_context(|| format!("Invalid private key {}", tls.private_key_path.display()))?
+    .ok_or_else(|| anyhow!("No private key in {}", tls.private_key_path.display()))?;
+
+    let server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certificate_chain, private_key)
+        .context("Certificate and private key do not match")?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
@@ -0,0 +1,213 @@
+/*
+    통합 테스트 하네스.
+
+    진짜 서버를 127.0.0.1:0에 띄우고, 소켓으로 프레임을 한 바이트씩 직접 만들어서 보냅니다.
+    브라우저나 라이브러리가 절대 보내지 않을 잘못된 프레임도 보낼 수 있어야 해서
+    클라이언트를 쓰지 않고 Frame::encode와 날 바이트를 써요.
+*/
+
+// 테스트 파일마다 이 모듈을 따로 컴파일해서, 어떤 파일에선 안 쓰는 함수가 생겨요.
+#![allow(dead_code)]
+
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use websocket_codec::{CloseFrame, Decoder, Frame, Message, MessageAssembler, Opcode, Role};
+use websocket_server::{init_db, serve_with_shutdown, Config};
+
+/// 이 시간 안에 아무것도 안오면 테스트 실패예요.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 테스트마다 새 DB 파일을 씁니다. 테스트가 끝나면 지워요.
+pub struct TestServer {
+    pub address: SocketAddr,
+    db_path: PathBuf,
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.db_path);
+    }
+}
+
+pub async fn start_server(config: Config) -> TestServer {
+    start_server_with_shutdown(config, std::future::pending())
+        .await
+        .0
+}
+
+/// `shutdown`이 끝나면 서버가 꺼져요. 돌려주는 JoinHandle로 다 꺼졌는지 기다릴 수 있어요.
+pub async fn start_server_with_shutdown(
+    config: Config,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> (TestServer, tokio::task::JoinHandle<anyhow::Result<()>>) {
+    static NEXT_DB_ID: AtomicU64 = AtomicU64::new(0);
+
+    let db_path = std::env::temp_dir().join(format!(
+        "websocket-server-test-{}-{}.sqlite",
+        std::process::id(),
+        NEXT_DB_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let db = init_db(&format!("sqlite:{}?mode=rwc", db_path.display()))
+        .await
+        .unwrap();
+
+    let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = tcp_listener.local_addr().unwrap();
+    let server_task = tokio::spawn(serve_with_shutdown(tcp_listener, db, config, shutdown));
+
+    (TestServer { address, db_path }, server_task)
+}
+
+/// 요청 하나를 그대로 보내고, 서버가 연결을 닫을 때까지 받은 응답을 돌려줘요.
+pub async fn send_http_request(server: &TestServer, request: &str) -> String {
+    let mut stream = TcpStream::connect(server.address).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    with_timeout(stream.read_to_end(&mut response))
+        .await
+        .unwrap();
+    String::from_utf8(response).unwrap()
+}
+
+pub struct RawClient {
+    stream: TcpStream,
+    decoder: Decoder,
+    assembler: MessageAssembler,
+    pub handshake_response: String,
+}
+
+impl RawClient {
+    pub async fn connect(server: &TestServer) -> Self {
+        Self::connect_with_headers(server, "").await
+    }
+
+    /// `extra_headers`는 `Name: value\r\n` 모양으로 넣어주세요.
+    pub async fn connect_with_headers(server: &TestServer, extra_headers: &str) -> Self {
+        Self::connect_to(server, "/", extra_headers).await
+    }
+
+    /// `/rooms/rust`처럼 다른 경로로 연결해요.
+    pub async fn connect_to(server: &TestServer, path: &str, extra_headers: &str) -> Self {
+        let mut stream = TcpStream::connect(server.address).await.unwrap();
+        stream
+            .write_all(
+                format!(
+                    "GET {path} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                     Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\
+                     {extra_headers}\r\n"
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        // 응답 바로 뒤에 붙어올 프레임을 먹어버리지 않도록 한 바이트씩 읽어요.
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            response.push(with_timeout(stream.read_u8()).await.unwrap());
+        }
+        let handshake_response = String::from_utf8(response).unwrap();
+        assert!(
+            handshake_response.starts_with("HTTP/1.1 101"),
+            "{handshake_response}"
+        );
+
+        Self {
+            stream,
+            decoder: Decoder::new(Role::Client),
+            assembler: MessageAssembler::new(None, usize::MAX),
+            handshake_response,
+        }
+    }
+
+    pub async fn send_raw(&mut self, bytes: &[u8]) {
+        self.stream.write_all(bytes).await.unwrap();
+    }
+
+    /// 마스킹해서 보냅니다.
+    pub async fn send_frame(&mut self, frame: &Frame) {
+        self.send_raw(&frame.encode(Some([0x37, 0xfa, 0x21, 0x3d])))
+            .await;
+    }
+
+    pub async fn send(&mut self, opcode: Opcode, payload: &[u8]) {
+        self.send_frame(&Frame::new(opcode, payload.to_vec())).await;
+    }
+
+    pub async fn send_fragment(&mut self, opcode: Opcode, fin: bool, payload: &[u8]) {
+        self.send_frame(&Frame {
+            fin,
+            ..Frame::new(opcode, payload.to_vec())
+        })
+        .await;
+    }
+
+    /// 서버가 연결을 닫았으면 None.
+    pub async fn read_frame(&mut self) -> Option<Frame> {
+        loop {
+            if let Some(frame) = self.decoder.decode().unwrap() {
+                return Some(frame);
+            }
+
+            let mut buf = [0u8; 4096];
+            // 서버가 RST로 끊어도 닫힌 걸로 봐요.
+            let length = with_timeout(self.stream.read(&mut buf)).await.unwrap_or(0);
+            if length == 0 {
+                return None;
+            }
+            self.decoder.feed(&buf[..length]);
+        }
+    }
+
+    /// 다음 데이터 메시지. 서버가 쪼개서 보냈으면 합쳐서 돌려줘요.
+    pub async fn read_message(&mut self) -> Message {
+        loop {
+            let frame = self.read_frame().await.expect("connection closed");
+            assert!(
+                !frame.opcode.is_control(),
+                "expected a message, got {frame:?}"
+            );
+            if let Some(message) = self.assembler.push(frame).unwrap() {
+                return message;
+            }
+        }
+    }
+
+    pub async fn expect_pong(&mut self, payload: &[u8]) {
+        let frame = self.read_frame().await.expect("connection closed");
+        assert_eq!(frame, Frame::new(Opcode::Pong, payload.to_vec()));
+    }
+
+    /*
+        서버가 Close를 보내고 TCP까지 닫는지 확인합니다.
+        `code`가 None이면 payload 없는 Close를 기대해요.
+    */
+    pub async fn expect_close(&mut self, code: Option<u16>) -> CloseFrame {
+        let frame = self
+            .read_frame()
+            .await
+            .expect("connection closed without a Close frame");
+        assert_eq!(frame.opcode, Opcode::Close, "expected Close, got {frame:?}");
+        let close_frame = CloseFrame::parse(&frame.payload).unwrap();
+        assert_eq!(close_frame.code.map(|code| code.0), code, "{close_frame:?}");
+
+        // 서버는 Close를 보낸 뒤 더 보내는 게 없어야 하고, 곧 연결을 닫아야 해요.
+        self.stream.shutdown().await.unwrap();
+        assert_eq!(self.read_frame().await, None);
+        close_frame
+    }
+}
+
+async fn with_timeout<T>(future: impl std::future::Future<Output = T>) -> T {
+    tokio::time::timeout(READ_TIMEOUT, future)
+        .await
+        .expect("timed out waiting for the server")
+}
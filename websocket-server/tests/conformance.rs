@@ -0,0 +1,628 @@
+/*
+    Autobahn testsuite(https://github.com/crossbario/autobahn-testsuite)의 분류를 따라 만든 적합성 테스트.
+
+    번호는 Autobahn의 케이스 번호와 맞춰뒀어요. (1.x Framing, 2.x Pings/Pongs, ...)
+    Autobahn은 서버가 보낸 걸 그대로 돌려주는 echo 서버를 기대하지만 우리는 채팅 서버라서,
+    보낸 사람 말고 다른 사람(observer)에게 제대로 전달되는지로 확인합니다.
+*/
+
+mod common;
+
+use common::{start_server, RawClient, TestServer};
+use websocket_codec::{Message, Opcode};
+use websocket_server::Config;
+
+async fn server_with_observer() -> (TestServer, RawClient, RawClient) {
+    let server = start_server(Config::default()).await;
+    // 먼저 연결한 쪽이 먼저 목록에 들어가니, observer를 먼저 연결해야 메시지를 놓치지 않아요.
+    let observer = RawClient::connect(&server).await;
+    let client = RawClient::connect(&server).await;
+    (server, observer, client)
+}
+
+/// 1.x Framing: 길이 경계마다 payload가 그대로 전달되는지.
+mod framing {
+    use super::*;
+
+    #[tokio::test]
+    async fn case_1_1_text_messages_of_various_lengths() {
+        let (_server, mut observer, mut client) = server_with_observer().await;
+
+        // 7비트, 16비트, 64비트 길이 표현의 경계들
+        for length in [0, 125, 126, 127, 128, 65535, 65536] {
+            let text = "*".repeat(length);
+            client.send(Opcode::Text, text.as_bytes()).await;
+            assert_eq!(observer.read_message().await, Message::Text(text));
+        }
+    }
+
+    #[tokio::test]
+    async fn case_1_2_binary_messages_of_various_lengths() {
+        let (_server, mut observer, mut client) = server_with_observer().await;
+
+        for length in [0, 125, 126, 127, 128, 65535, 65536] {
+            let bytes = vec![0xfe; length];
+            client.send(Opcode::Binary, &bytes).await;
+            assert_eq!(observer.read_message().await, Message::Binary(bytes));
+        }
+    }
+
+    #[tokio::test]
+    async fn frame_split_into_single_bytes() {
+        let (_server, mut observer, mut client) = server_with_observer().await;
+
+        let bytes =
+            websocket_codec::Frame::new(Opcode::Text, b"Hello".to_vec()).encode(Some([1, 2, 3, 4]));
+        for byte in bytes {
+            client.send_raw(&[byte]).await;
+        }
+        assert_eq!(
+            observer.read_message().await,
+            Message::Text("Hello".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn several_frames_in_one_write() {
+        let (_server, mut observer, mut client) = server_with_observer().await;
+
+        let mask = Some([1, 2, 3, 4]);
+        let bytes = [
+            websocket_codec::Frame::new(Opcode::Text, b"one".to_vec()).encode(mask),
+            websocket_codec::Frame::new(Opcode::Text, b"two".to_vec()).encode(mask),
+        ]
+        .concat();
+        client.send_raw(&bytes).await;
+
+        assert_eq!(
+            observer.read_message().await,
+            Message::Text("one".to_string())
+        );
+        assert_eq!(
+            observer.read_message().await,
+            Message::Text("two".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn unmasked_frame_is_a_protocol_error() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send_raw(&[0x81, 0x02, b'h', b'i']).await;
+        client.expect_close(Some(1002)).await;
+    }
+
+    #[tokio::test]
+    async fn payload_length_with_most_significant_bit_set() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client
+            .send_raw(&[0x82, 0xff, 0x80, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4])
+            .await;
+        client.expect_close(Some(1002)).await;
+    }
+}
+
+/// 2.x Pings/Pongs
+mod pings_and_pongs {
+    use super::*;
+
+    #[tokio::test]
+    async fn case_2_1_ping_without_payload() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send(Opcode::Ping, b"").await;
+        client.expect_pong(b"").await;
+    }
+
+    #[tokio::test]
+    async fn case_2_2_and_2_4_ping_payload_is_echoed() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send(Opcode::Ping, b"Hello, world!").await;
+        client.expect_pong(b"Hello, world!").await;
+
+        let payload = [0xfe; 125];
+        client.send(Opcode::Ping, &payload).await;
+        client.expect_pong(&payload).await;
+    }
+
+    #[tokio::test]
+    async fn case_2_5_ping_longer_than_125_bytes() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send(Opcode::Ping, &[0xfe; 126]).await;
+        client.expect_close(Some(1002)).await;
+    }
+
+    #[tokio::test]
+    async fn case_2_7_unsolicited_pong_is_ignored() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send(Opcode::Pong, b"").await;
+        client.send(Opcode::Pong, b"unsolicited").await;
+        // Pong에는 대답하지 않으니 다음으로 오는 건 이 Ping의 Pong이어야 해요.
+        client.send(Opcode::Ping, b"after").await;
+        client.expect_pong(b"after").await;
+    }
+
+    #[tokio::test]
+    async fn case_2_10_many_pings_are_answered_in_order() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        for i in 0..10u8 {
+            client.send(Opcode::Ping, &[i]).await;
+        }
+        for i in 0..10u8 {
+            client.expect_pong(&[i]).await;
+        }
+    }
+}
+
+/// 3.x Reserved bits: 합의한 확장이 없으면 RSV1~3은 모두 0이어야 해요.
+mod reserved_bits {
+    use super::*;
+
+    async fn send_with_rsv(rsv: u8, opcode: u8) -> RawClient {
+        let server = start_server(Config::default()).await;
+        let mut client = RawClient::connect(&server).await;
+        client
+            .send_raw(&[0x80 | rsv << 4 | opcode, 0x80, 1, 2, 3, 4])
+            .await;
+        client
+    }
+
+    #[tokio::test]
+    async fn case_3_1_rsv1_without_extension() {
+        send_with_rsv(0b100, 1).await.expect_close(Some(1002)).await;
+    }
+
+    #[tokio::test]
+    async fn case_3_2_rsv2_after_a_valid_message() {
+        let (_server, mut observer, mut client) = server_with_observer().await;
+
+        client.send(Opcode::Text, b"Hello").await;
+        client.send_raw(&[0xa1, 0x80, 1, 2, 3, 4]).await;
+        client.expect_close(Some(1002)).await;
+
+        // 잘못된 프레임 전에 온 메시지는 그대로 전달돼요.
+        assert_eq!(
+            observer.read_message().await,
+            Message::Text("Hello".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn case_3_3_to_3_7_other_reserved_bits() {
+        for (rsv, opcode) in [(0b011, 1), (0b100, 2), (0b101, 2), (0b110, 9), (0b111, 8)] {
+            send_with_rsv(rsv, opcode)
+                .await
+                .expect_close(Some(1002))
+                .await;
+        }
+    }
+}
+
+/// 4.x Opcodes: 정의되지 않은 opcode
+mod opcodes {
+    use super::*;
+
+    #[tokio::test]
+    async fn case_4_1_reserved_non_control_opcodes() {
+        let server = start_server(Config::default()).await;
+        for opcode in 3..=7u8 {
+            let mut client = RawClient::connect(&server).await;
+            client.send_raw(&[0x80 | opcode, 0x80, 1, 2, 3, 4]).await;
+            client.expect_close(Some(1002)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn case_4_2_reserved_control_opcodes() {
+        let server = start_server(Config::default()).await;
+        for opcode in 11..=15u8 {
+            let mut client = RawClient::connect(&server).await;
+            client.send_raw(&[0x80 | opcode, 0x80, 1, 2, 3, 4]).await;
+            client.expect_close(Some(1002)).await;
+        }
+    }
+}
+
+/// 5.x Fragmentation
+mod fragmentation {
+    use super::*;
+
+    #[tokio::test]
+    async fn case_5_1_fragmented_ping() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send_fragment(Opcode::Ping, false, b"frag").await;
+        client.expect_close(Some(1002)).await;
+    }
+
+    #[tokio::test]
+    async fn case_5_3_fragmented_text_message() {
+        let (_server, mut observer, mut client) = server_with_observer().await;
+
+        client.send_fragment(Opcode::Text, false, b"frag").await;
+        client
+            .send_fragment(Opcode::Continuation, false, b"ment")
+            .await;
+        client
+            .send_fragment(Opcode::Continuation, true, b"ed")
+            .await;
+        assert_eq!(
+            observer.read_message().await,
+            Message::Text("fragmented".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn case_5_6_ping_between_fragments() {
+        let (_server, mut observer, mut client) = server_with_observer().await;
+
+        client.send_fragment(Opcode::Text, false, b"frag").await;
+        client.send(Opcode::Ping, b"in between").await;
+        client.expect_pong(b"in between").await;
+        client
+            .send_fragment(Opcode::Continuation, true, b"ment")
+            .await;
+        assert_eq!(
+            observer.read_message().await,
+            Message::Text("fragment".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn case_5_9_continuation_without_a_message() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client
+            .send_fragment(Opcode::Continuation, true, b"orphan")
+            .await;
+        client.expect_close(Some(1002)).await;
+    }
+
+    #[tokio::test]
+    async fn case_5_18_new_message_before_the_last_fragment() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send_fragment(Opcode::Text, false, b"first").await;
+        client.send_fragment(Opcode::Text, true, b"second").await;
+        client.expect_close(Some(1002)).await;
+    }
+
+    #[tokio::test]
+    async fn case_5_20_empty_fragments() {
+        let (_server, mut observer, mut client) = server_with_observer().await;
+
+        client.send_fragment(Opcode::Binary, false, b"").await;
+        client.send_fragment(Opcode::Continuation, false, b"").await;
+        client.send_fragment(Opcode::Continuation, true, b"").await;
+        assert_eq!(observer.read_message().await, Message::Binary(vec![]));
+    }
+}
+
+/// 6.x UTF-8 handling
+mod utf8 {
+    use super::*;
+
+    #[tokio::test]
+    async fn case_6_2_valid_utf8_split_at_every_byte() {
+        let (_server, mut observer, mut client) = server_with_observer().await;
+
+        let text = "Hello-µ@ßöäüàá-UTF-8!! 안녕 🦀";
+        let bytes = text.as_bytes();
+        client.send_fragment(Opcode::Text, false, &bytes[..1]).await;
+        for byte in &bytes[1..bytes.len() - 1] {
+            client
+                .send_fragment(Opcode::Continuation, false, &[*byte])
+                .await;
+        }
+        client
+            .send_fragment(Opcode::Continuation, true, &bytes[bytes.len() - 1..])
+            .await;
+
+        assert_eq!(
+            observer.read_message().await,
+            Message::Text(text.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn case_6_3_invalid_utf8_in_a_single_frame() {
+        let server = start_server(Config::default()).await;
+
+        let invalid_payloads: [&[u8]; 6] = [
+            b"\xce\xba\xe1\xbd\xb9\xcf\x83\xce\xbc\xce\xb5\xed\xa0\x80edited",
+            // overlong encoding
+            b"\xc0\xaf",
+            // UTF-16 surrogate
+            b"\xed\xa0\x80",
+            // U+10FFFF보다 큰 코드 포인트
+            b"\xf4\x90\x80\x80",
+            // 이어지는 바이트가 혼자 나옴
+            b"\x80",
+            // 끝에서 잘린 글자
+            b"\xe2\x82",
+        ];
+        for payload in invalid_payloads {
+            let mut client = RawClient::connect(&server).await;
+            client.send(Opcode::Text, payload).await;
+            client.expect_close(Some(1007)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn case_6_4_invalid_utf8_is_detected_before_the_last_fragment() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client
+            .send_fragment(Opcode::Text, false, "κόσμε".as_bytes())
+            .await;
+        client
+            .send_fragment(Opcode::Continuation, false, b"\xf4\x90\x80\x80")
+            .await;
+        // 마지막 조각을 보내지 않아도 서버는 바로 끊어야 해요.
+        client.expect_close(Some(1007)).await;
+    }
+
+    #[tokio::test]
+    async fn text_message_ending_in_the_middle_of_a_character() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send_fragment(Opcode::Text, false, b"abc").await;
+        client
+            .send_fragment(Opcode::Continuation, true, b"\xe2\x82")
+            .await;
+        client.expect_close(Some(1007)).await;
+    }
+}
+
+/// 7.x Close handling
+mod close {
+    use super::*;
+
+    #[tokio::test]
+    async fn case_7_1_1_close_is_echoed() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send(Opcode::Close, b"\x03\xe8bye").await;
+        client.expect_close(Some(1000)).await;
+    }
+
+    #[tokio::test]
+    async fn case_7_1_2_data_after_close_is_ignored() {
+        let (server, mut observer, mut client) = server_with_observer().await;
+
+        client.send(Opcode::Close, b"\x03\xe8").await;
+        client.send(Opcode::Text, b"too late").await;
+        client.send(Opcode::Ping, b"too late").await;
+        client.expect_close(Some(1000)).await;
+
+        // observer에게도 아무것도 안 갔어야 해요. 다음으로 받는 건 새 클라이언트의 메시지.
+        let mut another = RawClient::connect(&server).await;
+        another.send(Opcode::Text, b"next").await;
+        assert_eq!(
+            observer.read_message().await,
+            Message::Text("next".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn case_7_3_1_close_without_payload() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send(Opcode::Close, b"").await;
+        client.expect_close(None).await;
+    }
+
+    #[tokio::test]
+    async fn case_7_3_2_close_with_one_byte_payload() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client.send(Opcode::Close, b"\x03").await;
+        client.expect_close(Some(1002)).await;
+    }
+
+    #[tokio::test]
+    async fn case_7_3_6_close_reason_longer_than_control_frame_limit() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        let mut payload = b"\x03\xe8".to_vec();
+        payload.extend_from_slice(&[b'*'; 124]);
+        client.send(Opcode::Close, &payload).await;
+        client.expect_close(Some(1002)).await;
+    }
+
+    #[tokio::test]
+    async fn case_7_5_1_close_reason_with_invalid_utf8() {
+        let (_server, _observer, mut client) = server_with_observer().await;
+
+        client
+            .send(
+                Opcode::Close,
+                b"\x03\xe8\xce\xba\xe1\xbd\xb9\xcf\x83\xce\xbc\xce\xb5\xed\xa0\x80",
+            )
+            .await;
+        client.expect_close(Some(1007)).await;
+    }
+
+    #[tokio::test]
+    async fn case_7_7_valid_close_codes_are_echoed() {
+        let server = start_server(Config::default()).await;
+        for code in [
+            1000u16, 1001, 1002, 1003, 1007, 1008, 1009, 1010, 1011, 3000, 3999, 4000, 4999,
+        ] {
+            let mut client = RawClient::connect(&server).await;
+            client.send(Opcode::Close, &code.to_be_bytes()).await;
+            client.expect_close(Some(code)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn case_7_9_invalid_close_codes() {
+        let server = start_server(Config::default()).await;
+        for code in [
+            0u16, 999, 1004, 1005, 1006, 1015, 1016, 1100, 2000, 2999, 5000, 65535,
+        ] {
+            let mut client = RawClient::connect(&server).await;
+            client.send(Opcode::Close, &code.to_be_bytes()).await;
+            client.expect_close(Some(1002)).await;
+        }
+    }
+}
+
+/// 9.x Limits: 설정한 크기를 넘는 프레임과 메시지는 1009로 끊어요.
+mod limits {
+    use super::*;
+
+    fn limited_config() -> Config {
+        Config {
+            max_inbound_frame_size: 1024,
+            max_inbound_message_size: 4096,
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn frame_at_the_limit_is_accepted() {
+        let server = start_server(limited_config()).await;
+        let mut observer = RawClient::connect(&server).await;
+        let mut client = RawClient::connect(&server).await;
+
+        client.send(Opcode::Binary, &[0; 1024]).await;
+        assert_eq!(
+            observer.read_message().await,
+            Message::Binary(vec![0; 1024])
+        );
+    }
+
+    #[tokio::test]
+    async fn frame_over_the_limit() {
+        let server = start_server(limited_config()).await;
+        let mut client = RawClient::connect(&server).await;
+
+        client.send(Opcode::Binary, &[0; 1025]).await;
+        client.expect_close(Some(1009)).await;
+    }
+
+    #[tokio::test]
+    async fn huge_length_is_rejected_before_the_payload_arrives() {
+        let server = start_server(limited_config()).await;
+        let mut client = RawClient::connect(&server).await;
+
+        // 2^62바이트를 보낸다고 해놓고 실제로는 안 보내요.
+        client
+            .send_raw(&[0x82, 0xff, 0x40, 0, 0, 0, 0, 0, 0, 0])
+            .await;
+        client.expect_close(Some(1009)).await;
+    }
+
+    #[tokio::test]
+    async fn fragmented_message_over_the_limit() {
+        let server = start_server(limited_config()).await;
+        let mut client = RawClient::connect(&server).await;
+
+        client
+            .send_fragment(Opcode::Binary, false, &[0; 1024])
+            .await;
+        for _ in 0..3 {
+            client
+                .send_fragment(Opcode::Continuation, false, &[0; 1024])
+                .await;
+        }
+        client.send_fragment(Opcode::Continuation, true, &[0]).await;
+        client.expect_close(Some(1009)).await;
+    }
+}
+
+/// 12.x / 13.x permessage-deflate
+mod compression {
+    use super::*;
+    use websocket_codec::{
+        deflate::{DeflateConfig, Deflater},
+        Frame, Role,
+    };
+
+    const OFFER: &str = "Sec-WebSocket-Extensions: permessage-deflate\r\n";
+
+    fn deflater() -> Deflater {
+        let config = DeflateConfig::from_response("permessage-deflate").unwrap();
+        Deflater::new(&config, Role::Client)
+    }
+
+    #[tokio::test]
+    async fn compressed_messages_are_delivered_decompressed() {
+        let server = start_server(Config::default()).await;
+        let mut observer = RawClient::connect(&server).await;
+        let mut client = RawClient::connect_with_headers(&server, OFFER).await;
+        assert!(client
+            .handshake_response
+            .contains("Sec-WebSocket-Extensions: permessage-deflate"));
+
+        // 사전을 이어서 쓰니(context takeover) 같은 Deflater로 여러 메시지를 보내봐요.
+        let mut deflater = deflater();
+        for text in ["Hello", "Hello", "", "안녕하세요 안녕하세요"] {
+            let frame = Frame {
+                rsv1: true,
+                ..Frame::new(Opcode::Text, deflater.compress(text.as_bytes()).unwrap())
+            };
+            client.send_frame(&frame).await;
+            assert_eq!(
+                observer.read_message().await,
+                Message::Text(text.to_string())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rsv1_on_a_continuation_frame() {
+        let server = start_server(Config::default()).await;
+        let mut client = RawClient::connect_with_headers(&server, OFFER).await;
+
+        client.send_fragment(Opcode::Text, false, b"").await;
+        client
+            .send_frame(&Frame {
+                rsv1: true,
+                ..Frame::new(Opcode::Continuation, vec![])
+            })
+            .await;
+        client.expect_close(Some(1002)).await;
+    }
+
+    #[tokio::test]
+    async fn invalid_compressed_data() {
+        let server = start_server(Config::default()).await;
+        let mut client = RawClient::connect_with_headers(&server, OFFER).await;
+
+        client
+            .send_frame(&Frame {
+                rsv1: true,
+                ..Frame::new(Opcode::Binary, vec![0xff; 16])
+            })
+            .await;
+        client.expect_close(Some(1007)).await;
+    }
+
+    #[tokio::test]
+    async fn decompressed_message_over_the_limit() {
+        let server = start_server(Config {
+            max_inbound_message_size: 4096,
+            ..Config::default()
+        })
+        .await;
+        let mut client = RawClient::connect_with_headers(&server, OFFER).await;
+
+        // 몇십 바이트로 압축되지만 풀면 제한을 넘는 메시지
+        let compressed = deflater().compress(&[0; 100_000]).unwrap();
+        assert!(compressed.len() < 4096);
+        client
+            .send_frame(&Frame {
+                rsv1: true,
+                ..Frame::new(Opcode::Binary, compressed)
+            })
+            .await;
+        client.expect_close(Some(1009)).await;
+    }
+}
@@ -0,0 +1,133 @@
+/*
+    chat.v2를 고르면 JSON 봉투로 주고받고, 고르지 않은 클라이언트는 예전처럼 내용만 주고받는지.
+*/
+
+mod common;
+
+use common::{start_server, RawClient};
+use serde_json::{json, Value};
+use websocket_codec::{Message, Opcode};
+use websocket_server::Config;
+
+const CHAT_V2: &str = "Sec-WebSocket-Protocol: chat.v2\r\n";
+
+async fn read_envelope(client: &mut RawClient) -> Value {
+    let Message::Text(text) = client.read_message().await else {
+        panic!("expected a text frame");
+    };
+    serde_json::from_str(&text).unwrap()
+}
+
+#[tokio::test]
+async fn chat_v2_clients_talk_in_envelopes_with_raw_clients() {
+    let server = start_server(Config::default()).await;
+    let mut alice = RawClient::connect_to(&server, "/rooms/rust?nick=alice", CHAT_V2).await;
+    assert!(alice
+        .handshake_response
+        .contains("Sec-WebSocket-Protocol: chat.v2\r\n"));
+    let mut guest = RawClient::connect_to(&server, "/rooms/rust", "").await;
+
+    guest.send(Opcode::Text, b"hi").await;
+    let envelope = read_envelope(&mut alice).await;
+    assert!(envelope["id"].is_i64(), "{envelope}");
+    assert!(envelope["timestamp"].is_i64(), "{envelope}");
+    assert_eq!(envelope["v"], 2);
+    assert_eq!(envelope["type"], "message");
+    assert_eq!(envelope["room"], "rust");
+    assert_eq!(envelope["author"], Value::Null);
+    assert_eq!(envelope["body"], "hi");
+
+    // 예전 클라이언트는 내용만 받아요.
+    alice
+        .send(
+            Opcode::Text,
+            json!({"type": "message", "body": "hello"})
+                .to_string()
+                .as_bytes(),
+        )
+        .await;
+    assert_eq!(
+        guest.read_message().await,
+        Message::Text("hello".to_string())
+    );
+
+    guest.send(Opcode::Text, b"/nick bob").await;
+    assert_eq!(
+        guest.read_message().await,
+        Message::Text("* You are now bob".to_string())
+    );
+    let envelope = read_envelope(&mut alice).await;
+    assert_eq!(envelope["type"], "notice");
+    assert_eq!(envelope["id"], Value::Null);
+    assert_eq!(envelope["body"], "* bob joined");
+
+    guest.send(Opcode::Text, b"again").await;
+    assert_eq!(read_envelope(&mut alice).await["author"], "bob");
+}
+
+#[tokio::test]
+async fn malformed_envelopes_get_an_error_reply() {
+    let server = start_server(Config::default()).await;
+    let mut alice = RawClient::connect_with_headers(&server, CHAT_V2).await;
+    let mut guest = RawClient::connect(&server).await;
+
+    for text in [
+        "hello",
+        r#"{"type":"message"}"#,
+        r#"{"type":"message","body":"hi","extra":1}"#,
+        r#"{"v":3,"type":"message","body":"hi"}"#,
+    ] {
+        alice.send(Opcode::Text, text.as_bytes()).await;
+        let envelope = read_envelope(&mut alice).await;
+        assert_eq!(envelope["type"], "error", "{text}: {envelope}");
+        assert_eq!(envelope["room"], "lobby");
+    }
+
+    alice
+        .send(
+            Opcode::Text,
+            json!({"type": "nick", "body": "bad name"})
+                .to_string()
+                .as_bytes(),
+        )
+        .await;
+    assert_eq!(read_envelope(&mut alice).await["type"], "error");
+    alice
+        .send(
+            Opcode::Text,
+            json!({"type": "nick", "body": "alice"})
+                .to_string()
+                .as_bytes(),
+        )
+        .await;
+    let envelope = read_envelope(&mut alice).await;
+    assert_eq!(envelope["type"], "notice");
+    assert_eq!(envelope["body"], "* You are now alice");
+
+    // 잘못 보낸 것들은 아무에게도 가지 않았어요.
+    alice
+        .send(
+            Opcode::Text,
+            json!({"type": "message", "body": "still here"})
+                .to_string()
+                .as_bytes(),
+        )
+        .await;
+    assert_eq!(
+        guest.read_message().await,
+        Message::Text("* alice joined".to_string())
+    );
+    assert_eq!(
+        guest.read_message().await,
+        Message::Text("still here".to_string())
+    );
+}
+
+#[tokio::test]
+async fn chat_v2_does_not_accept_binary() {
+    let server = start_server(Config::default()).await;
+    let mut client = RawClient::connect_with_headers(&server, CHAT_V2).await;
+
+    client.send(Opcode::Binary, &[1, 2, 3]).await;
+    client.expect_close(Some(1003)).await;
+}
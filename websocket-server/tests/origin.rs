@@ -0,0 +1,96 @@
+/*
+    Origin 허락 목록과 CORS 테스트.
+    브라우저처럼 Origin 헤더를 붙여서 핸드셰이크와 HTTP 요청을 보내봐요.
+*/
+
+mod common;
+
+use common::{send_http_request, start_server, RawClient, TestServer};
+use websocket_server::Config;
+
+async fn server_allowing(allowed_origins: &[&str]) -> TestServer {
+    start_server(Config {
+        allowed_origins: allowed_origins
+            .iter()
+            .map(|origin| origin.to_string())
+            .collect(),
+        ..Config::default()
+    })
+    .await
+}
+
+fn upgrade_request(origin: &str) -> String {
+    format!(
+        "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\
+         Origin: {origin}\r\n\r\n"
+    )
+}
+
+#[tokio::test]
+async fn websocket_from_another_site_is_forbidden() {
+    let server = server_allowing(&["https://chat.example.com"]).await;
+
+    for origin in ["https://evil.example.com", "null", "http://localhost:1234"] {
+        let response = send_http_request(&server, &upgrade_request(origin)).await;
+        assert!(
+            response.starts_with("HTTP/1.1 403 Forbidden\r\n"),
+            "{origin}: {response}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn websocket_from_allowed_origins() {
+    let server = server_allowing(&["https://chat.example.com"]).await;
+
+    // 우리가 준 페이지, 허락 목록에 있는 사이트, 브라우저가 아닌 클라이언트
+    RawClient::connect_with_headers(&server, "Origin: http://localhost\r\n").await;
+    RawClient::connect_with_headers(&server, "Origin: https://chat.example.com\r\n").await;
+    RawClient::connect(&server).await;
+
+    let server = server_allowing(&["*"]).await;
+    RawClient::connect_with_headers(&server, "Origin: https://evil.example.com\r\n").await;
+}
+
+#[tokio::test]
+async fn cors_headers_only_for_allowed_origins() {
+    let server = server_allowing(&["https://chat.example.com"]).await;
+
+    let response = send_http_request(
+        &server,
+        "GET /metrics HTTP/1.1\r\nHost: localhost\r\nOrigin: https://chat.example.com\r\n\r\n",
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("\r\nAccess-Control-Allow-Origin: https://chat.example.com\r\n"));
+    assert!(response.contains("\r\nVary: Origin\r\n"));
+
+    // 응답은 주지만, 브라우저가 스크립트에게 보여주지 않도록 CORS 헤더를 안 붙여요.
+    let response = send_http_request(
+        &server,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://evil.example.com\r\n\r\n",
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(!response.contains("Access-Control-Allow-Origin"));
+}
+
+#[tokio::test]
+async fn preflight_requests() {
+    let server = server_allowing(&["https://chat.example.com"]).await;
+    let preflight = |origin: &str| {
+        format!(
+            "OPTIONS /metrics HTTP/1.1\r\nHost: localhost\r\nOrigin: {origin}\r\n\
+             Access-Control-Request-Method: GET\r\n\r\n"
+        )
+    };
+
+    let response = send_http_request(&server, &preflight("https://chat.example.com")).await;
+    assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+    assert!(response.contains("\r\nAccess-Control-Allow-Origin: https://chat.example.com\r\n"));
+    assert!(response.contains("\r\nAccess-Control-Allow-Methods: GET, OPTIONS\r\n"));
+
+    let response = send_http_request(&server, &preflight("https://evil.example.com")).await;
+    assert!(response.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+}
@@ -0,0 +1,105 @@
+/*
+    닉네임을 정하고 바꾸면 같은 방 사람들이 알림을 받는지, 보낸 사람이 기록에 남는지.
+*/
+
+mod common;
+
+use common::{send_http_request, start_server, RawClient, TestServer};
+use websocket_codec::{Message, Opcode};
+use websocket_server::Config;
+
+async fn get(server: &TestServer, path: &str) -> String {
+    send_http_request(
+        server,
+        &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+    )
+    .await
+}
+
+fn text(text: &str) -> Message {
+    Message::Text(text.to_string())
+}
+
+#[tokio::test]
+async fn named_users_are_announced_and_listed() {
+    let server = start_server(Config::default()).await;
+    let mut guest = RawClient::connect(&server).await;
+    let mut alice = RawClient::connect_to(&server, "/?nick=alice", "").await;
+    assert_eq!(guest.read_message().await, text("* alice joined"));
+
+    // 손님은 닉네임을 정할 때 들어온 것처럼 알려져요.
+    guest.send(Opcode::Text, b"/nick bob").await;
+    assert_eq!(guest.read_message().await, text("* You are now bob"));
+    assert_eq!(alice.read_message().await, text("* bob joined"));
+
+    guest.send(Opcode::Text, b"/nick alice").await;
+    assert_eq!(
+        guest.read_message().await,
+        text("* Nickname alice is taken")
+    );
+    guest.send(Opcode::Text, b"/nick <b>").await;
+    assert!(matches!(
+        guest.read_message().await,
+        Message::Text(notice) if notice.starts_with("* Nickname must be")
+    ));
+
+    alice.send(Opcode::Text, b"/nick carol").await;
+    assert_eq!(alice.read_message().await, text("* You are now carol"));
+    assert_eq!(guest.read_message().await, text("* alice is now carol"));
+
+    let response = get(&server, "/rooms/lobby/users").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: application/json\r\n"));
+    let bob_at = response.find(r#""nickname":"bob""#).unwrap();
+    let carol_at = response.find(r#""nickname":"carol""#).unwrap();
+    assert!(bob_at < carol_at, "{response}");
+
+    drop(alice);
+    assert_eq!(guest.read_message().await, text("* carol left"));
+}
+
+#[tokio::test]
+async fn messages_are_saved_with_their_author() {
+    let server = start_server(Config::default()).await;
+    let mut alice = RawClient::connect_to(&server, "/?nick=alice", "").await;
+    let mut guest = RawClient::connect(&server).await;
+
+    // 다른 사람들이 받는 메시지는 예전처럼 내용만 있어요.
+    alice.send(Opcode::Text, b"hello").await;
+    assert_eq!(guest.read_message().await, text("hello"));
+    guest.send(Opcode::Text, b"hi").await;
+    assert_eq!(alice.read_message().await, text("hi"));
+
+    let page = get(&server, "/").await;
+    assert!(page.contains("<li>alice: hello</li>"));
+    assert!(page.contains("<li>hi</li>"));
+}
+
+#[tokio::test]
+async fn invalid_or_taken_nicknames_are_refused() {
+    let server = start_server(Config::default()).await;
+    let _alice = RawClient::connect_to(&server, "/rooms/rust?nick=alice", "").await;
+
+    let upgrade = |path: &str| {
+        format!(
+            "GET {path} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+    };
+    let response = send_http_request(&server, &upgrade("/rooms/rust?nick=alice")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 409 Conflict\r\n"),
+        "{response}"
+    );
+    let response = send_http_request(&server, &upgrade("/?nick=a%20b")).await;
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{response}"
+    );
+
+    // 다른 방에서는 같은 이름을 써도 돼요.
+    let _other_alice = RawClient::connect_to(&server, "/?nick=alice", "").await;
+    let users = get(&server, "/rooms/rust/users").await;
+    assert_eq!(users.matches(r#""id":"#).count(), 1, "{users}");
+    assert!(users.ends_with(r#","nickname":"alice"}]"#), "{users}");
+}
@@ -0,0 +1,219 @@
+/*
+    마지막으로 받은 메시지 id를 알려주면, 그 뒤의 메시지를 빠짐없이 겹치지 않고 순서대로 받는지.
+*/
+
+mod common;
+
+use common::{send_http_request, start_server, RawClient, TestServer};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use websocket_codec::{Message, Opcode};
+use websocket_server::{Config, SlowConsumerPolicy};
+
+const CHAT_V2: &str = "Sec-WebSocket-Protocol: chat.v2\r\n";
+
+fn text(text: &str) -> Message {
+    Message::Text(text.to_string())
+}
+
+async fn read_envelope(client: &mut RawClient) -> Value {
+    let Message::Text(text) = client.read_message().await else {
+        panic!("expected a text frame");
+    };
+    serde_json::from_str(&text).unwrap()
+}
+
+/// `sender`가 보낸 메시지들의 DB id를 chat.v2로 듣고 있는 `watcher`에게서 알아내요.
+async fn send_and_collect_ids(
+    sender: &mut RawClient,
+    watcher: &mut RawClient,
+    texts: &[&str],
+) -> Vec<i64> {
+    let mut ids = vec![];
+    for body in texts {
+        sender.send(Opcode::Text, body.as_bytes()).await;
+        let envelope = read_envelope(watcher).await;
+        assert_eq!(envelope["body"], *body);
+        ids.push(envelope["id"].as_i64().unwrap());
+    }
+    ids
+}
+
+async fn setup() -> (TestServer, RawClient, RawClient, Vec<i64>) {
+    let server = start_server(Config::default()).await;
+    let mut watcher = RawClient::connect_with_headers(&server, CHAT_V2).await;
+    let mut bob = RawClient::connect(&server).await;
+    let ids = send_and_collect_ids(&mut bob, &mut watcher, &["one", "two", "three"]).await;
+    (server, watcher, bob, ids)
+}
+
+#[tokio::test]
+async fn since_on_upgrade_replays_missed_messages_before_live_ones() {
+    let (server, mut watcher, mut bob, ids) = setup().await;
+
+    let mut carol = RawClient::connect_to(&server, &format!("/?since={}", ids[0]), "").await;
+    assert_eq!(carol.read_message().await, text("two"));
+    assert_eq!(carol.read_message().await, text("three"));
+
+    send_and_collect_ids(&mut bob, &mut watcher, &["four", "five"]).await;
+    assert_eq!(carol.read_message().await, text("four"));
+    assert_eq!(carol.read_message().await, text("five"));
+
+    // 아직 없는 id를 부탁해도, 새로 오는 메시지는 빠짐없이 받아요.
+    let mut dave = RawClient::connect_to(&server, "/?since=999999", "").await;
+    bob.send(Opcode::Text, b"six").await;
+    assert_eq!(dave.read_message().await, text("six"));
+    assert_eq!(carol.read_message().await, text("six"));
+
+    // 연결 뒤에 보낸 `/resume`은 받지 않아요.
+    carol.send(Opcode::Text, b"/resume 0").await;
+    assert_eq!(
+        carol.read_message().await,
+        text("* Resume must be the first message")
+    );
+}
+
+#[tokio::test]
+async fn first_message_can_ask_to_resume() {
+    let (server, mut watcher, mut bob, ids) = setup().await;
+
+    let mut carol = RawClient::connect(&server).await;
+    carol
+        .send(Opcode::Text, format!("/resume {}", ids[1]).as_bytes())
+        .await;
+    assert_eq!(carol.read_message().await, text("three"));
+    send_and_collect_ids(&mut bob, &mut watcher, &["four"]).await;
+    assert_eq!(carol.read_message().await, text("four"));
+
+    let mut dave = RawClient::connect_with_headers(&server, CHAT_V2).await;
+    dave.send(
+        Opcode::Text,
+        json!({"type": "resume", "since": ids[0]})
+            .to_string()
+            .as_bytes(),
+    )
+    .await;
+    let mut replayed = vec![];
+    for _ in 0..3 {
+        let envelope = read_envelope(&mut dave).await;
+        assert_eq!(envelope["type"], "message");
+        replayed.push(envelope["body"].as_str().unwrap().to_string());
+    }
+    assert_eq!(replayed, ["two", "three", "four"]);
+
+    // 이어받은 뒤에 보낸 메시지는 평소처럼 다른 사람에게 가요.
+    carol.send(Opcode::Text, b"hi").await;
+    assert_eq!(bob.read_message().await, text("hi"));
+    assert_eq!(read_envelope(&mut dave).await["body"], "hi");
+}
+
+#[tokio::test]
+async fn invalid_since_is_rejected() {
+    let (server, _watcher, _bob, ids) = setup().await;
+
+    let response = send_http_request(
+        &server,
+        "GET /?since=-1 HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+        "{response}"
+    );
+
+    let mut carol = RawClient::connect(&server).await;
+    carol.send(Opcode::Text, b"/resume soon").await;
+    assert_eq!(
+        carol.read_message().await,
+        text("* Usage: /resume <message id>")
+    );
+
+    // 페이지는 담은 메시지 뒤부터 받도록 연결해요.
+    let page = send_http_request(&server, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+    assert!(
+        page.contains(&format!("params.set('since', '{}')", ids[2])),
+        "{page}"
+    );
+}
+
+#[tokio::test]
+async fn replies_are_not_lost_while_catching_up() {
+    const HISTORY_COUNT: usize = 1000;
+    let server = start_server(Config::default()).await;
+    let mut bob = RawClient::connect(&server).await;
+    // DB에서 여러 번 나눠 읽어야 할 만큼 쌓아둬요.
+    for number in 0..HISTORY_COUNT {
+        bob.send(Opcode::Text, number.to_string().as_bytes()).await;
+    }
+    // 앞의 것들이 다 저장된 뒤에야 대답이 와요.
+    bob.send(Opcode::Text, b"/nick bob").await;
+    assert_eq!(bob.read_message().await, text("* You are now bob"));
+
+    let mut carol = RawClient::connect_to(&server, "/?since=0", CHAT_V2).await;
+    carol.send(Opcode::Text, b"not an envelope").await;
+
+    // 대답은 버려지지 않고, 더 오래된 메시지들을 다 보낸 다음에 와요.
+    for number in 0..HISTORY_COUNT {
+        let envelope = read_envelope(&mut carol).await;
+        assert_eq!(envelope["type"], "message", "{envelope}");
+        assert_eq!(envelope["body"], number.to_string());
+    }
+    assert_eq!(read_envelope(&mut carol).await["type"], "error");
+}
+
+/*
+    DropOldest로 버려진 메시지가 있으면, 이미 보낸 메시지들의 id 사이에 못 보낸 게 끼어 있어요.
+    이어받을 때 그걸 이미 보낸 걸로 치면 안 돼요.
+    소켓 버퍼(수 MB)까지 꽉 채워야 서버의 큐가 차니까 압축 없이 큰 메시지를 많이 보내요. (slow_consumer.rs)
+*/
+#[tokio::test]
+async fn resume_after_dropped_messages_has_no_gaps() {
+    const MESSAGE_COUNT: usize = 256;
+    const MESSAGE_SIZE: usize = 64 * 1024;
+    let numbered_message = |number: usize| {
+        let mut text = format!("{number:05}");
+        text.extend(std::iter::repeat_n('x', MESSAGE_SIZE - text.len()));
+        text
+    };
+    let message_number = |message: Message| -> usize {
+        let Message::Text(text) = message else {
+            panic!("expected text, got {message:?}");
+        };
+        text[..5].parse().unwrap()
+    };
+
+    let server = start_server(Config {
+        channel_capacity: 4,
+        slow_consumer_policy: SlowConsumerPolicy::DropOldest,
+        permessage_deflate: false,
+        ..Config::default()
+    })
+    .await;
+    let mut slow = RawClient::connect(&server).await;
+    let mut fast = RawClient::connect(&server).await;
+    let mut client = RawClient::connect(&server).await;
+    for number in 0..MESSAGE_COUNT {
+        let text = numbered_message(number);
+        client.send(Opcode::Text, text.as_bytes()).await;
+        assert_eq!(fast.read_message().await, Message::Text(text));
+    }
+
+    // 버려진 메시지 뒤의 것까지 받아요. 그러면 서버가 보낸 범위 안에 못 보낸 게 생겨요.
+    let mut received = HashSet::new();
+    let mut last = None;
+    loop {
+        let number = message_number(slow.read_message().await);
+        received.insert(number);
+        if number > last.map_or(0, |last| last + 1) {
+            break;
+        }
+        last = Some(number);
+    }
+
+    // 처음부터 다시 보내달래요. 빠진 게 하나라도 있으면 기다리다 끝나요.
+    slow.send(Opcode::Text, b"/resume 0").await;
+    while received.len() < MESSAGE_COUNT {
+        received.insert(message_number(slow.read_message().await));
+    }
+}
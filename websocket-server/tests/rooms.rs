@@ -0,0 +1,106 @@
+/*
+    `/rooms/{name}`으로 연결하면 그 방 사람들끼리만 이야기하는지.
+*/
+
+mod common;
+
+use common::{send_http_request, start_server, RawClient, TestServer};
+use std::time::Duration;
+use websocket_codec::{Message, Opcode};
+use websocket_server::Config;
+
+async fn get(server: &TestServer, path: &str) -> String {
+    send_http_request(
+        server,
+        &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+    )
+    .await
+}
+
+/// 나간 유저는 조금 뒤에 방에서 빠지니, 원하는 목록이 될 때까지 기다려요.
+async fn wait_for_rooms(server: &TestServer, expected: &str) {
+    for _ in 0..100 {
+        if get(server, "/rooms").await.ends_with(expected) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!(
+        "rooms never became {expected}: {}",
+        get(server, "/rooms").await
+    );
+}
+
+#[tokio::test]
+async fn messages_stay_in_their_room() {
+    let server = start_server(Config::default()).await;
+    let mut alice = RawClient::connect_to(&server, "/rooms/rust", "").await;
+    let mut bob = RawClient::connect_to(&server, "/rooms/rust", "").await;
+    let mut carol = RawClient::connect(&server).await;
+    let mut dave = RawClient::connect(&server).await;
+
+    alice.send(Opcode::Text, b"in rust").await;
+    assert_eq!(
+        bob.read_message().await,
+        Message::Text("in rust".to_string())
+    );
+    // 먼저 보낸 "in rust"가 lobby로 갔다면 dave가 그걸 먼저 받았을 거예요.
+    carol.send(Opcode::Text, b"in lobby").await;
+    assert_eq!(
+        dave.read_message().await,
+        Message::Text("in lobby".to_string())
+    );
+    bob.send(Opcode::Text, b"back in rust").await;
+    assert_eq!(
+        alice.read_message().await,
+        Message::Text("back in rust".to_string())
+    );
+
+    let rust_page = get(&server, "/rooms/rust").await;
+    assert!(rust_page.contains("<li>in rust</li>"));
+    assert!(!rust_page.contains("<li>in lobby</li>"));
+    let lobby_page = get(&server, "/").await;
+    assert!(lobby_page.contains("<li>in lobby</li>"));
+    assert!(!lobby_page.contains("<li>in rust</li>"));
+}
+
+#[tokio::test]
+async fn active_rooms_are_listed() {
+    let server = start_server(Config::default()).await;
+    wait_for_rooms(&server, "\r\n\r\n[]").await;
+
+    let alice = RawClient::connect_to(&server, "/rooms/rust", "").await;
+    let bob = RawClient::connect_to(&server, "/rooms/rust", "").await;
+    let carol = RawClient::connect(&server).await;
+
+    let response = get(&server, "/rooms").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: application/json\r\n"));
+    assert!(response.ends_with(r#"[{"name":"lobby","users":1},{"name":"rust","users":2}]"#));
+
+    drop(alice);
+    drop(carol);
+    wait_for_rooms(&server, r#"[{"name":"rust","users":1}]"#).await;
+    drop(bob);
+    wait_for_rooms(&server, "\r\n\r\n[]").await;
+}
+
+#[tokio::test]
+async fn upgrade_to_unknown_path_is_not_found() {
+    let server = start_server(Config::default()).await;
+
+    for path in ["/rooms/", "/rooms/a/b", "/rooms/%3Cscript%3E", "/chat"] {
+        let response = send_http_request(
+            &server,
+            &format!(
+                "GET {path} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+            ),
+        )
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{path}: {response}"
+        );
+    }
+}
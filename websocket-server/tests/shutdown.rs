@@ -0,0 +1,78 @@
+/*
+    서버를 끌 때(SIGINT, SIGTERM) 클라이언트들에게 1001로 Close를 보내고 정리하는지.
+    테스트에서는 시그널 대신 oneshot으로 끄라고 알려줘요.
+*/
+
+mod common;
+
+use common::{start_server_with_shutdown, RawClient};
+use std::time::Duration;
+use tokio::{net::TcpStream, sync::oneshot};
+use websocket_codec::{Message, Opcode};
+use websocket_server::Config;
+
+#[tokio::test]
+async fn clients_receive_going_away_and_server_stops() {
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let (server, server_task) = start_server_with_shutdown(Config::default(), async {
+        let _ = shutdown_rx.await;
+    })
+    .await;
+
+    let mut observer = RawClient::connect(&server).await;
+    let mut client = RawClient::connect(&server).await;
+    client.send(Opcode::Text, b"before shutdown").await;
+    assert_eq!(
+        observer.read_message().await,
+        Message::Text("before shutdown".to_string())
+    );
+
+    shutdown_tx.send(()).unwrap();
+
+    for mut user in [observer, client] {
+        let close_frame = user.expect_close(Some(1001)).await;
+        assert_eq!(close_frame.reason, "Server is shutting down");
+    }
+    tokio::time::timeout(Duration::from_secs(5), server_task)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    // 리스너도 닫혔어요.
+    assert!(TcpStream::connect(server.address).await.is_err());
+}
+
+#[tokio::test]
+async fn unresponsive_clients_do_not_block_shutdown() {
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let (server, server_task) = start_server_with_shutdown(
+        Config {
+            // Close에 대답이 없어도 한참 기다려줄 설정이지만, 전체 제한이 먼저 끝나요.
+            close_timeout: Duration::from_secs(60),
+            shutdown_timeout: Duration::from_millis(200),
+            ..Config::default()
+        },
+        async {
+            let _ = shutdown_rx.await;
+        },
+    )
+    .await;
+
+    // 연결만 하고 아무것도 읽지도, 대답하지도 않는 클라이언트
+    let mut silent = RawClient::connect(&server).await;
+    // 핸드셰이크도 안 끝낸 연결
+    let _half_open = TcpStream::connect(server.address).await.unwrap();
+
+    shutdown_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), server_task)
+        .await
+        .expect("server did not stop within shutdown_timeout")
+        .unwrap()
+        .unwrap();
+
+    // 기다려주지 않은 연결도 남김없이 닫혀요.
+    let frame = silent.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, Opcode::Close);
+    assert_eq!(silent.read_frame().await, None);
+}
@@ -0,0 +1,140 @@
+/*
+    메시지를 읽지 않는 느린 유저가 있어도 다른 유저들은 계속 메시지를 받는지,
+    그리고 느린 유저는 slow_consumer_policy대로 처리되는지.
+
+    소켓 버퍼(수 MB)까지 꽉 채워야 서버의 큐가 차니까 압축 없이 큰 메시지를 많이 보내요.
+*/
+
+mod common;
+
+use common::{send_http_request, start_server, RawClient, TestServer};
+use websocket_codec::{CloseCode, CloseFrame, Message, Opcode};
+use websocket_server::{Config, SlowConsumerPolicy};
+
+const MESSAGE_COUNT: usize = 256;
+const MESSAGE_SIZE: usize = 64 * 1024;
+
+async fn start_slow_consumer_server(policy: SlowConsumerPolicy) -> TestServer {
+    start_server(Config {
+        channel_capacity: 4,
+        slow_consumer_policy: policy,
+        permessage_deflate: false,
+        ..Config::default()
+    })
+    .await
+}
+
+/// 앞에 번호를 붙여서 몇번째 메시지인지 알 수 있게 해요.
+fn numbered_message(number: usize) -> String {
+    let mut text = format!("{number:05}");
+    text.extend(std::iter::repeat_n('x', MESSAGE_SIZE - text.len()));
+    text
+}
+
+fn message_number(message: &Message) -> usize {
+    let Message::Text(text) = message else {
+        panic!("expected text, got {message:?}");
+    };
+    text[..5].parse().unwrap()
+}
+
+/// `slow`는 그동안 하나도 읽지 않아요. `fast`는 하나도 빠짐없이 바로바로 받아야 해요.
+async fn send_numbered_messages(client: &mut RawClient, fast: &mut RawClient) {
+    for number in 0..MESSAGE_COUNT {
+        let text = numbered_message(number);
+        client.send(Opcode::Text, text.as_bytes()).await;
+        assert_eq!(fast.read_message().await, Message::Text(text));
+    }
+}
+
+#[tokio::test]
+async fn slow_consumer_is_disconnected_with_policy_violation() {
+    let server = start_slow_consumer_server(SlowConsumerPolicy::Disconnect).await;
+    let mut slow = RawClient::connect(&server).await;
+    let mut fast = RawClient::connect(&server).await;
+    let mut client = RawClient::connect(&server).await;
+
+    send_numbered_messages(&mut client, &mut fast).await;
+
+    // 소켓 버퍼에 들어가 있던 메시지들 뒤에 1008이 와요.
+    let mut received = vec![];
+    let close_frame = loop {
+        let frame = slow.read_frame().await.expect("connection closed");
+        match frame.opcode {
+            Opcode::Text => received.push(message_number(&Message::Text(
+                String::from_utf8(frame.payload).unwrap(),
+            ))),
+            Opcode::Close => break CloseFrame::parse(&frame.payload).unwrap(),
+            opcode => panic!("unexpected {opcode:?}"),
+        }
+    };
+    assert_eq!(close_frame.code, Some(CloseCode::POLICY_VIOLATION));
+    assert!(received.len() < MESSAGE_COUNT);
+    assert!(received
+        .iter()
+        .enumerate()
+        .all(|(index, &number)| index == number));
+
+    // 끊긴 유저 말고는 계속 주고받아요.
+    client.send(Opcode::Text, b"still here").await;
+    assert_eq!(
+        fast.read_message().await,
+        Message::Text("still here".to_string())
+    );
+    let metrics = send_http_request(&server, "GET /metrics HTTP/1.1\r\n\r\n").await;
+    assert!(!metrics.contains("websocket_slow_consumer_disconnects_total 0\n"));
+}
+
+#[tokio::test]
+async fn slow_consumer_misses_oldest_messages_with_drop_oldest() {
+    let server = start_slow_consumer_server(SlowConsumerPolicy::DropOldest).await;
+    let mut slow = RawClient::connect(&server).await;
+    let mut fast = RawClient::connect(&server).await;
+    let mut client = RawClient::connect(&server).await;
+
+    send_numbered_messages(&mut client, &mut fast).await;
+
+    // 중간이 빠지긴 해도 순서는 그대로고, 마지막 메시지까지 와요.
+    let mut received = vec![];
+    while received.last() != Some(&(MESSAGE_COUNT - 1)) {
+        received.push(message_number(&slow.read_message().await));
+    }
+    assert!(received.len() < MESSAGE_COUNT);
+    assert!(received.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[tokio::test]
+async fn slow_consumer_catches_up_from_db_with_replay_from_db() {
+    let server = start_slow_consumer_server(SlowConsumerPolicy::ReplayFromDb).await;
+    let mut slow = RawClient::connect(&server).await;
+    let mut fast = RawClient::connect(&server).await;
+    let mut client = RawClient::connect(&server).await;
+
+    send_numbered_messages(&mut client, &mut fast).await;
+    // 밀려 있는 동안 자기가 보낸 메시지는 돌려받지 않아요.
+    slow.send(Opcode::Text, b"from the slow one").await;
+    assert_eq!(
+        fast.read_message().await,
+        Message::Text("from the slow one".to_string())
+    );
+    client.send(Opcode::Text, b"after").await;
+    assert_eq!(
+        fast.read_message().await,
+        Message::Text("after".to_string())
+    );
+
+    // 빠짐없이, 겹치지 않고, 순서대로.
+    for number in 0..MESSAGE_COUNT {
+        assert_eq!(
+            slow.read_message().await,
+            Message::Text(numbered_message(number))
+        );
+    }
+    assert_eq!(
+        slow.read_message().await,
+        Message::Text("after".to_string())
+    );
+
+    let metrics = send_http_request(&server, "GET /metrics HTTP/1.1\r\n\r\n").await;
+    assert!(!metrics.contains("websocket_slow_consumer_replays_total 0\n"));
+}
@@ -0,0 +1,166 @@
+/*
+    TLS(https://, wss://) 테스트.
+
+    테스트할 때마다 자체 서명 인증서를 새로 만들어서 임시 파일에 쓰고,
+    클라이언트는 그 인증서 하나만 믿도록 설정해요.
+*/
+
+mod common;
+
+use common::{start_server, TestServer};
+use std::{path::PathBuf, sync::Arc};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+use websocket_codec::{
+    client::{Client, ClientOptions},
+    Message,
+};
+use websocket_server::{init_db, serve, Config, TlsConfig};
+
+/// 임시 파일에 쓴 인증서와 키. 테스트가 끝나면 지워요.
+struct TestCertificate {
+    certificate_path: PathBuf,
+    private_key_path: PathBuf,
+    connector: TlsConnector,
+}
+
+impl TestCertificate {
+    fn generate(name: &str) -> Self {
+        let certified_key =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+
+        let prefix = format!("websocket-server-test-{}-{name}", std::process::id());
+        let certificate_path = std::env::temp_dir().join(format!("{prefix}.crt"));
+        let private_key_path = std::env::temp_dir().join(format!("{prefix}.key"));
+        std::fs::write(&certificate_path, certified_key.cert.pem()).unwrap();
+        std::fs::write(&private_key_path, certified_key.key_pair.serialize_pem()).unwrap();
+
+        let mut root_cert_store = RootCertStore::empty();
+        root_cert_store
+            .add(certified_key.cert.der().clone())
+            .unwrap();
+        let client_config = ClientConfig::builder()
+            .with_root_certificates(root_cert_store)
+            .with_no_client_auth();
+
+        Self {
+            certificate_path,
+            private_key_path,
+            connector: TlsConnector::from(Arc::new(client_config)),
+        }
+    }
+
+    fn tls_config(&self) -> TlsConfig {
+        TlsConfig {
+            certificate_path: self.certificate_path.clone(),
+            private_key_path: self.private_key_path.clone(),
+        }
+    }
+
+    async fn start_server(&self) -> TestServer {
+        start_server(Config {
+            tls: Some(self.tls_config()),
+            ..Config::default()
+        })
+        .await
+    }
+
+    async fn connect(&self, server: &TestServer) -> TlsStream<TcpStream> {
+        let tcp_stream = TcpStream::connect(server.address).await.unwrap();
+        self.connector
+            .connect(ServerName::try_from("localhost").unwrap(), tcp_stream)
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for TestCertificate {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.certificate_path);
+        let _ = std::fs::remove_file(&self.private_key_path);
+    }
+}
+
+#[tokio::test]
+async fn index_page_is_served_over_https() {
+    let certificate = TestCertificate::generate("index");
+    let server = certificate.start_server().await;
+
+    let mut stream = certificate.connect(&server).await;
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    // https로 받은 페이지는 wss://로 연결해야 해요.
+    assert!(response.contains("location.protocol === 'https:' ? 'wss' : 'ws'"));
+}
+
+#[tokio::test]
+async fn websocket_messages_are_exchanged_over_tls() {
+    let certificate = TestCertificate::generate("websocket");
+    let server = certificate.start_server().await;
+
+    let mut observer = Client::handshake(
+        certificate.connect(&server).await,
+        "localhost",
+        "/",
+        ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+    let mut client = Client::handshake(
+        certificate.connect(&server).await,
+        "localhost",
+        "/",
+        ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    // 압축도 TLS 위에서 똑같이 돼요.
+    let text = "안녕하세요 ".repeat(1000);
+    client.send_text(text.clone()).await.unwrap();
+    assert_eq!(observer.recv().await.unwrap(), Some(Message::Text(text)));
+}
+
+#[tokio::test]
+async fn plain_tcp_is_not_answered_when_tls_is_enabled() {
+    let certificate = TestCertificate::generate("plain");
+    let server = certificate.start_server().await;
+
+    let mut stream = TcpStream::connect(server.address).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = vec![];
+    let _ = stream.read_to_end(&mut response).await;
+
+    assert!(!response.starts_with(b"HTTP/1.1"));
+}
+
+#[tokio::test]
+async fn serve_fails_without_certificate() {
+    let db = init_db("sqlite::memory:").await.unwrap();
+    let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = Config {
+        tls: Some(TlsConfig {
+            certificate_path: "/nonexistent/server.crt".into(),
+            private_key_path: "/nonexistent/server.key".into(),
+        }),
+        ..Config::default()
+    };
+
+    assert!(serve(tcp_listener, db, config).await.is_err());
+}